@@ -0,0 +1,10 @@
+CREATE TABLE webhook_deliveries (
+    id SERIAL,
+    url Text NOT NULL,
+    payload Text NOT NULL,
+    attempts integer DEFAULT 0 NOT NULL,
+    next_attempt timestamp with time zone NOT NULL,
+    last_error Text DEFAULT NULL,
+    PRIMARY KEY (id)
+);
+CREATE INDEX webhook_delivery_next_attempt ON webhook_deliveries (next_attempt);
@@ -0,0 +1,14 @@
+CREATE TABLE device_groups (
+    id SERIAL,
+    name Text NOT NULL,
+    PRIMARY KEY (id),
+    UNIQUE (name)
+);
+
+CREATE TABLE device_groups_devices (
+    device_group integer,
+    device integer,
+    PRIMARY KEY (device_group, device),
+    FOREIGN KEY (device_group) REFERENCES device_groups (id) ON DELETE CASCADE,
+    FOREIGN KEY (device) REFERENCES devices (id) ON DELETE CASCADE
+);
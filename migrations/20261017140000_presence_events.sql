@@ -0,0 +1,12 @@
+CREATE TABLE presence_events (
+    time timestamp with time zone NOT NULL,
+    device integer NOT NULL,
+    station integer NOT NULL,
+    kind Text NOT NULL,
+    reason Text NOT NULL,
+    FOREIGN KEY (device) REFERENCES devices (id) ON DELETE CASCADE,
+    FOREIGN KEY (station) REFERENCES stations (id) ON DELETE CASCADE
+);
+CREATE INDEX presence_event_device ON presence_events (device, time);
+
+SELECT create_hypertable('presence_events', 'time');
@@ -0,0 +1,14 @@
+CREATE TABLE hook_executions (
+    id SERIAL,
+    name Text NOT NULL,
+    command Text NOT NULL,
+    event Text NOT NULL,
+    started_at timestamp with time zone NOT NULL,
+    duration_ms bigint NOT NULL,
+    exit_code integer,
+    timed_out BOOLEAN NOT NULL,
+    stdout Text NOT NULL,
+    stderr Text NOT NULL,
+    PRIMARY KEY (id)
+);
+CREATE INDEX hook_execution_started_at ON hook_executions (started_at);
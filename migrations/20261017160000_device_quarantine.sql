@@ -0,0 +1,2 @@
+ALTER TABLE devices ADD COLUMN vendor Text DEFAULT NULL;
+ALTER TABLE devices ADD COLUMN quarantined BOOLEAN DEFAULT FALSE NOT NULL;
@@ -0,0 +1,12 @@
+ALTER TABLE stations ADD COLUMN protected BOOLEAN DEFAULT NULL;
+
+CREATE TABLE rogue_access_points (
+    bssid VARCHAR(17) NOT NULL,
+    station integer NOT NULL,
+    ssid VARCHAR(32),
+    reason Text NOT NULL,
+    first_seen timestamp with time zone NOT NULL,
+    last_seen timestamp with time zone NOT NULL,
+    PRIMARY KEY (bssid, station),
+    FOREIGN KEY (station) REFERENCES stations (id) ON DELETE CASCADE
+);
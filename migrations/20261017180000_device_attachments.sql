@@ -0,0 +1,11 @@
+CREATE TABLE device_attachments (
+    id SERIAL,
+    device integer NOT NULL,
+    station integer NOT NULL,
+    attached_at timestamp with time zone NOT NULL,
+    detached_at timestamp with time zone,
+    PRIMARY KEY (id),
+    FOREIGN KEY (device) REFERENCES devices (id) ON DELETE CASCADE,
+    FOREIGN KEY (station) REFERENCES stations (id) ON DELETE CASCADE
+);
+CREATE INDEX device_attachment_device ON device_attachments (device, attached_at);
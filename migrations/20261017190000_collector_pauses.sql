@@ -0,0 +1,6 @@
+CREATE TABLE collector_pauses (
+    id SERIAL,
+    created_at timestamp with time zone NOT NULL,
+    until timestamp with time zone NOT NULL,
+    PRIMARY KEY (id)
+);
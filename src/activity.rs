@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::config::ActivityThreshold;
+use crate::db::models::*;
+use crate::events::Event;
+use crate::traffic::get_minute;
+
+/// The activity of a single device on a single station for a single threshold.
+struct Activity {
+    device: Device,
+    station: Station,
+    /// `None` until the device has been active or idle for long enough.
+    active: Option<bool>,
+    /// The start of the minutes in a row with traffic above the active threshold.
+    above_since: Option<DateTime<Utc>>,
+    /// The start of the minutes in a row with traffic below the idle threshold.
+    below_since: Option<DateTime<Utc>>,
+    /// The latest minute we got traffic for.
+    last_minute: DateTime<Utc>,
+}
+
+/// A threshold with its durations already converted.
+struct Threshold {
+    config: ActivityThreshold,
+    active_for: TimeDelta,
+    idle_for: TimeDelta,
+}
+
+/// Decides whether devices are active or idle, based on their traffic per minute.
+///
+/// This is fed with the [Event::Traffic] of each finished minute.
+/// Minutes without any traffic don't have an event, which is why they're detected via [ActivityTracker::check].
+pub struct ActivityTracker {
+    thresholds: Vec<Threshold>,
+    /// The activity for each `(threshold index, device id, station id)` tuple.
+    activities: HashMap<(usize, i32, i32), Activity>,
+}
+
+impl ActivityTracker {
+    pub fn new(thresholds: &[ActivityThreshold]) -> Result<Self> {
+        let mut converted = Vec::new();
+        for threshold in thresholds {
+            converted.push(Threshold {
+                config: threshold.clone(),
+                active_for: TimeDelta::try_minutes(threshold.active_for).context(format!(
+                    "Invalid 'active_for' in activity {}",
+                    threshold.name
+                ))?,
+                idle_for: TimeDelta::try_minutes(threshold.idle_for)
+                    .context(format!("Invalid 'idle_for' in activity {}", threshold.name))?,
+            });
+        }
+
+        Ok(ActivityTracker {
+            thresholds: converted,
+            activities: HashMap::new(),
+        })
+    }
+
+    /// Update the activity of a device with the traffic of a finished minute.
+    /// Returns a [Event::DeviceActive] for each threshold that has been exceeded for long enough.
+    pub fn handle_event(&mut self, event: &Event) -> Vec<Event> {
+        let (device, station, minute, bytes) = match event {
+            Event::Traffic {
+                device,
+                station,
+                time,
+                bytes_per_minute,
+                ..
+            } => (device, station, *time, *bytes_per_minute),
+            _ => return Vec::new(),
+        };
+
+        let mut events = Vec::new();
+        for (index, threshold) in self.thresholds.iter().enumerate() {
+            if !matches_device(&threshold.config, device) {
+                continue;
+            }
+
+            let activity = self
+                .activities
+                .entry((index, device.id, station.id))
+                .or_insert_with(|| Activity {
+                    device: device.clone(),
+                    station: station.clone(),
+                    active: None,
+                    above_since: None,
+                    below_since: None,
+                    last_minute: minute,
+                });
+            activity.device = device.clone();
+            activity.station = station.clone();
+
+            // Late traffic of a minute we already evaluated is ignored.
+            if minute < activity.last_minute {
+                continue;
+            }
+            activity.skip_to(minute);
+            activity.last_minute = minute;
+
+            if bytes > threshold.config.active_above {
+                activity.below_since = None;
+                let since = *activity.above_since.get_or_insert(minute);
+
+                // The current minute counts as well, since it's already over.
+                let duration = minute + one_minute() - since;
+                if activity.active != Some(true) && duration >= threshold.active_for {
+                    activity.active = Some(true);
+                    events.push(Event::DeviceActive {
+                        device: activity.device.clone(),
+                        station: activity.station.clone(),
+                        time: minute + one_minute(),
+                        activity: threshold.config.name.clone(),
+                        bytes_per_minute: bytes,
+                    });
+                }
+            } else if bytes < threshold.config.idle_below {
+                activity.above_since = None;
+                activity.below_since.get_or_insert(minute);
+            } else {
+                activity.above_since = None;
+                activity.below_since = None;
+            }
+        }
+
+        events
+    }
+
+    /// Check which devices have been idle for long enough.
+    /// Returns a [Event::DeviceIdle] for each device that has been active before.
+    pub fn check(&mut self, now: DateTime<Utc>) -> Vec<Event> {
+        let current_minute = get_minute(now);
+
+        let mut events = Vec::new();
+        for ((index, _, _), activity) in self.activities.iter_mut() {
+            let threshold = &self.thresholds[*index];
+
+            // The traffic of a minute is reported once it's over.
+            // All finished minutes without any reported traffic didn't have any.
+            let last_finished = current_minute - one_minute();
+            if activity.last_minute < last_finished {
+                activity.skip_to(current_minute);
+                activity.last_minute = last_finished;
+            }
+
+            let since = match activity.below_since {
+                Some(since) => since,
+                None => continue,
+            };
+            if activity.active == Some(false) || current_minute - since < threshold.idle_for {
+                continue;
+            }
+
+            // Devices that haven't been active since we started are silently marked as idle.
+            let was_active = activity.active == Some(true);
+            activity.active = Some(false);
+            if was_active {
+                events.push(Event::DeviceIdle {
+                    device: activity.device.clone(),
+                    station: activity.station.clone(),
+                    time: now,
+                    activity: threshold.config.name.clone(),
+                });
+            }
+        }
+
+        events
+    }
+}
+
+impl Activity {
+    /// All minutes between the last minute with traffic and the given minute had no traffic.
+    /// Those count as idle minutes and interrupt any streak of active minutes.
+    fn skip_to(&mut self, minute: DateTime<Utc>) {
+        let first_empty = self.last_minute + one_minute();
+        if first_empty < minute {
+            self.above_since = None;
+            self.below_since.get_or_insert(first_empty);
+        }
+    }
+}
+
+/// Check whether a threshold applies to a device.
+fn matches_device(threshold: &ActivityThreshold, device: &Device) -> bool {
+    match &threshold.device {
+        Some(name) => {
+            device.nickname.as_ref() == Some(name) || &device.mac_address.to_string() == name
+        }
+        None => true,
+    }
+}
+
+fn one_minute() -> TimeDelta {
+    TimeDelta::try_minutes(1).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::events::EventKind;
+
+    fn time(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 17, hour, minute, 0).unwrap()
+    }
+
+    fn device() -> Device {
+        Device {
+            id: 1,
+            mac_address: "02:00:00:00:00:01".parse().unwrap(),
+            nickname: None,
+            description: None,
+            watch: true,
+            vendor: None,
+            quarantined: false,
+        }
+    }
+
+    fn station() -> Station {
+        Station {
+            id: 1,
+            mac_address: "02:00:00:00:01:01".parse().unwrap(),
+            ssid: Some("home".into()),
+            channel: 6,
+            power_level: None,
+            nickname: None,
+            description: None,
+            watch: true,
+            protected: Some(true),
+        }
+    }
+
+    fn tracker() -> ActivityTracker {
+        ActivityTracker::new(&[ActivityThreshold {
+            name: "streaming".into(),
+            device: None,
+            active_above: 1000,
+            active_for: 3,
+            idle_below: 100,
+            idle_for: 5,
+        }])
+        .unwrap()
+    }
+
+    fn traffic(tracker: &mut ActivityTracker, minute: DateTime<Utc>, bytes: i32) -> Vec<EventKind> {
+        let event = Event::Traffic {
+            device: device(),
+            station: station(),
+            time: minute,
+            bytes_per_minute: bytes,
+            last_seen: minute,
+        };
+        tracker
+            .handle_event(&event)
+            .iter()
+            .map(Event::kind)
+            .collect()
+    }
+
+    fn check(tracker: &mut ActivityTracker, now: DateTime<Utc>) -> Vec<EventKind> {
+        tracker.check(now).iter().map(Event::kind).collect()
+    }
+
+    #[test]
+    fn active_after_a_streak() {
+        let mut tracker = tracker();
+        assert!(traffic(&mut tracker, time(12, 0), 2000).is_empty());
+        assert!(traffic(&mut tracker, time(12, 1), 2000).is_empty());
+        assert_eq!(
+            traffic(&mut tracker, time(12, 2), 2000),
+            vec![EventKind::Active]
+        );
+        // Devices are only active once.
+        assert!(traffic(&mut tracker, time(12, 3), 2000).is_empty());
+    }
+
+    #[test]
+    fn streak_is_interrupted_by_less_traffic() {
+        let mut tracker = tracker();
+        traffic(&mut tracker, time(12, 0), 2000);
+        traffic(&mut tracker, time(12, 1), 2000);
+        assert!(traffic(&mut tracker, time(12, 2), 500).is_empty());
+        assert!(traffic(&mut tracker, time(12, 3), 2000).is_empty());
+        assert!(traffic(&mut tracker, time(12, 4), 2000).is_empty());
+        assert_eq!(
+            traffic(&mut tracker, time(12, 5), 2000),
+            vec![EventKind::Active]
+        );
+    }
+
+    #[test]
+    fn streak_is_interrupted_by_minutes_without_traffic() {
+        let mut tracker = tracker();
+        traffic(&mut tracker, time(12, 0), 2000);
+        traffic(&mut tracker, time(12, 1), 2000);
+        assert!(traffic(&mut tracker, time(12, 3), 2000).is_empty());
+        assert!(traffic(&mut tracker, time(12, 4), 2000).is_empty());
+        assert_eq!(
+            traffic(&mut tracker, time(12, 5), 2000),
+            vec![EventKind::Active]
+        );
+    }
+
+    #[test]
+    fn idle_without_traffic() {
+        let mut tracker = tracker();
+        for minute in 0..3 {
+            traffic(&mut tracker, time(12, minute), 2000);
+        }
+
+        // The first minute without traffic is 12:03.
+        assert!(check(&mut tracker, time(12, 4)).is_empty());
+        assert!(check(&mut tracker, time(12, 7)).is_empty());
+        assert_eq!(check(&mut tracker, time(12, 8)), vec![EventKind::Idle]);
+        assert!(check(&mut tracker, time(12, 9)).is_empty());
+    }
+
+    #[test]
+    fn idle_with_little_traffic() {
+        let mut tracker = tracker();
+        for minute in 0..3 {
+            traffic(&mut tracker, time(12, minute), 2000);
+        }
+        for minute in 3..8 {
+            assert!(traffic(&mut tracker, time(12, minute), 50).is_empty());
+        }
+
+        assert_eq!(check(&mut tracker, time(12, 8)), vec![EventKind::Idle]);
+    }
+
+    #[test]
+    fn inactive_devices_silently_become_idle() {
+        let mut tracker = tracker();
+        traffic(&mut tracker, time(12, 0), 50);
+        assert!(check(&mut tracker, time(13, 0)).is_empty());
+        assert!(tracker
+            .activities
+            .values()
+            .all(|activity| activity.active == Some(false)));
+    }
+}
@@ -1,12 +1,149 @@
-use clap::{ArgAction, Parser};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use chrono::TimeDelta;
+use clap::{ArgAction, Parser, Subcommand};
 
 #[derive(Parser, Debug)]
-#[command(name = "Sniff", about = "Track wifi devices", author, version)]
+#[command(
+    name = "Sniff",
+    about = "Track wifi devices",
+    author,
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct CliArguments {
     /// Verbose mode (-v, -vv, -vvv)
-    #[arg(short, long, action = ArgAction::Count)]
+    #[arg(short, long, action = ArgAction::Count, global = true)]
     pub verbose: u8,
 
-    /// The device you want to listen on (e.g. [wlan0, wlp3s0])
-    pub device: String,
+    /// The devices you want to listen on (e.g. [wlan0, wlp3s0])
+    /// The first one watches the stations, all others sweep the channels.
+    /// Defaults to the interfaces of the config.
+    pub devices: Vec<String>,
+
+    /// Replay a recorded radiotap capture (pcap or pcapng) instead of listening on a device.
+    #[arg(short, long, conflicts_with = "devices")]
+    pub read: Option<PathBuf>,
+
+    /// Replay the capture with the recorded delays between frames instead of as fast as possible.
+    #[arg(long, requires = "read")]
+    pub realtime: bool,
+
+    #[command(subcommand)]
+    pub cmd: Option<SubCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SubCommand {
+    /// Show the history of devices joining and leaving stations.
+    Events {
+        /// Only show events of the device with this nickname or mac address.
+        #[arg(short, long)]
+        device: Option<String>,
+
+        /// Only show events newer than this (e.g. `30m`, `12h` or `2d`).
+        #[arg(short, long, default_value = "1d", value_parser = parse_duration)]
+        since: TimeDelta,
+    },
+    /// Show which stations devices have been attached to.
+    Attachments {
+        /// Only show attachments of the device with this nickname or mac address.
+        #[arg(short, long)]
+        device: Option<String>,
+
+        /// Only show attachments newer than this (e.g. `30m`, `12h` or `2d`).
+        #[arg(short, long, default_value = "1d", value_parser = parse_duration)]
+        since: TimeDelta,
+    },
+    /// Show all new devices, that are quarantined until they're acknowledged.
+    Quarantined,
+    /// Release a device from quarantine.
+    Acknowledge {
+        /// The mac address or nickname of the device.
+        device: String,
+
+        /// Don't watch the device after releasing it.
+        #[arg(long)]
+        ignore: bool,
+    },
+    /// Pause the collector for a while.
+    /// No sweeps are done, no new devices are registered and no output is produced.
+    Pause {
+        /// How long to pause (e.g. `30m`, `2h`).
+        #[arg(value_parser = parse_duration)]
+        duration: TimeDelta,
+    },
+    /// Resume a paused collector.
+    Resume,
+    /// Show the latest executions of hook and rule commands.
+    Executions {
+        /// The amount of executions to show.
+        #[arg(short, long, default_value_t = 20)]
+        limit: i64,
+
+        /// Also show the output of each command.
+        #[arg(short, long)]
+        output: bool,
+    },
+}
+
+/// Parse a duration such as `90s`, `30m`, `12h`, `2d` or `1w`.
+fn parse_duration(input: &str) -> Result<TimeDelta> {
+    let input = input.trim();
+    let unit_start = input
+        .find(|c: char| !c.is_ascii_digit())
+        .context("Missing unit, use one of s, m, h, d or w")?;
+    let (amount, unit) = input.split_at(unit_start);
+    let amount: i64 = amount.parse().context("Invalid number")?;
+
+    let duration = match unit {
+        "s" => TimeDelta::try_seconds(amount),
+        "m" => TimeDelta::try_minutes(amount),
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        "w" => TimeDelta::try_weeks(amount),
+        _ => bail!("Unknown unit {}, use one of s, m, h, d or w", unit),
+    };
+
+    duration.context("Duration is too large")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(
+            parse_duration("90s").unwrap(),
+            TimeDelta::try_seconds(90).unwrap()
+        );
+        assert_eq!(
+            parse_duration("30m").unwrap(),
+            TimeDelta::try_minutes(30).unwrap()
+        );
+        assert_eq!(
+            parse_duration(" 12h ").unwrap(),
+            TimeDelta::try_hours(12).unwrap()
+        );
+        assert_eq!(
+            parse_duration("2d").unwrap(),
+            TimeDelta::try_days(2).unwrap()
+        );
+        assert_eq!(
+            parse_duration("1w").unwrap(),
+            TimeDelta::try_weeks(1).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert!(parse_duration("90").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("5y").is_err());
+        assert!(parse_duration("-5m").is_err());
+        assert!(parse_duration("99999999999999999w").is_err());
+    }
 }
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{Local, TimeDelta, Utc};
+
+use super::find_device;
+use wifitify::db::models::*;
+use wifitify::db::Connection;
+use wifitify::events::{device_name, station_name};
+
+/// Print which stations devices have been attached to in a given time frame.
+pub async fn print_attachments(
+    connection: &mut Connection,
+    device: Option<String>,
+    since: TimeDelta,
+) -> Result<()> {
+    let devices: HashMap<i32, Device> = Device::known_devices(connection)
+        .await?
+        .into_values()
+        .map(|device| (device.id, device))
+        .collect();
+    let stations: HashMap<i32, Station> = Station::known_stations(connection)
+        .await?
+        .into_values()
+        .map(|station| (station.id, station))
+        .collect();
+
+    let device_id = match device {
+        Some(name) => Some(find_device(devices.values(), &name)?.id),
+        None => None,
+    };
+
+    let attachments = Attachment::since(connection, Utc::now() - since, device_id).await?;
+    if attachments.is_empty() {
+        println!("No attachments found");
+        return Ok(());
+    }
+
+    for attachment in attachments {
+        let device = devices
+            .get(&attachment.device)
+            .map(device_name)
+            .unwrap_or_else(|| attachment.device.to_string());
+        let station = stations
+            .get(&attachment.station)
+            .map(station_name)
+            .unwrap_or_else(|| attachment.station.to_string());
+        let detached_at = match attachment.detached_at {
+            Some(time) => time.with_timezone(&Local).format("%H:%M:%S").to_string(),
+            None => "now".to_string(),
+        };
+
+        println!(
+            "{} - {:<8} {:<20} {}",
+            attachment
+                .attached_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            detached_at,
+            device,
+            station,
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{Local, TimeDelta, Utc};
+
+use super::find_device;
+use wifitify::db::models::*;
+use wifitify::db::Connection;
+use wifitify::events::{device_name, station_name};
+
+/// Print all presence events in a given time frame.
+pub async fn print_events(
+    connection: &mut Connection,
+    device: Option<String>,
+    since: TimeDelta,
+) -> Result<()> {
+    let devices: HashMap<i32, Device> = Device::known_devices(connection)
+        .await?
+        .into_values()
+        .map(|device| (device.id, device))
+        .collect();
+    let stations: HashMap<i32, Station> = Station::known_stations(connection)
+        .await?
+        .into_values()
+        .map(|station| (station.id, station))
+        .collect();
+
+    let device_id = match device {
+        Some(name) => Some(find_device(devices.values(), &name)?.id),
+        None => None,
+    };
+
+    let events = PresenceEvent::since(connection, Utc::now() - since, device_id).await?;
+    if events.is_empty() {
+        println!("No events found");
+        return Ok(());
+    }
+
+    for event in events {
+        let device = devices
+            .get(&event.device)
+            .map(device_name)
+            .unwrap_or_else(|| event.device.to_string());
+        let station = stations
+            .get(&event.station)
+            .map(station_name)
+            .unwrap_or_else(|| event.station.to_string());
+
+        println!(
+            "{}  {:<8} {:<20} {:<20} {}",
+            event.time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+            event.kind,
+            device,
+            station,
+            event.reason
+        );
+    }
+
+    Ok(())
+}
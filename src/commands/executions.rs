@@ -0,0 +1,49 @@
+use anyhow::Result;
+use chrono::Local;
+
+use wifitify::db::models::*;
+use wifitify::db::Connection;
+
+/// Print the latest executions of hook and rule commands.
+pub async fn print_executions(connection: &mut Connection, limit: i64, output: bool) -> Result<()> {
+    let executions = HookExecution::latest(connection, limit).await?;
+    if executions.is_empty() {
+        println!("No executions found");
+        return Ok(());
+    }
+
+    // Show the oldest execution first, just like the events command.
+    for execution in executions.into_iter().rev() {
+        let result = if execution.timed_out {
+            "timeout".to_string()
+        } else {
+            match execution.exit_code {
+                Some(code) => format!("exit {}", code),
+                None => "failed".to_string(),
+            }
+        };
+
+        println!(
+            "{}  {:<20} {:<13} {:<8} {:>6}ms",
+            execution
+                .started_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            execution.name,
+            execution.event,
+            result,
+            execution.duration_ms,
+        );
+
+        if output {
+            for line in execution.stdout.lines() {
+                println!("    stdout: {}", line);
+            }
+            for line in execution.stderr.lines() {
+                println!("    stderr: {}", line);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use anyhow::{bail, Result};
+
+use wifitify::db::models::Device;
+use wifitify::db::DbPool;
+
+use crate::cli::SubCommand;
+
+mod attachments;
+mod events;
+mod executions;
+mod pause;
+mod quarantine;
+
+/// Execute a subcommand instead of running the collector.
+pub async fn handle_command(command: SubCommand, pool: &DbPool) -> Result<()> {
+    let mut connection = pool.acquire().await?;
+
+    match command {
+        SubCommand::Events { device, since } => {
+            events::print_events(&mut connection, device, since).await
+        }
+        SubCommand::Attachments { device, since } => {
+            attachments::print_attachments(&mut connection, device, since).await
+        }
+        SubCommand::Quarantined => quarantine::print_quarantined(&mut connection).await,
+        SubCommand::Acknowledge { device, ignore } => {
+            quarantine::acknowledge(&mut connection, device, !ignore).await
+        }
+        SubCommand::Pause { duration } => pause::pause(&mut connection, duration).await,
+        SubCommand::Resume => pause::resume(&mut connection).await,
+        SubCommand::Executions { limit, output } => {
+            executions::print_executions(&mut connection, limit, output).await
+        }
+    }
+}
+
+/// Find a device by its nickname or mac address.
+fn find_device<'a>(
+    devices: impl IntoIterator<Item = &'a Device>,
+    name: &str,
+) -> Result<&'a Device> {
+    match devices.into_iter().find(|device| {
+        device.nickname.as_deref() == Some(name) || device.mac_address.to_string() == name
+    }) {
+        Some(device) => Ok(device),
+        None => bail!("Couldn't find device {}", name),
+    }
+}
@@ -0,0 +1,37 @@
+use anyhow::Result;
+use chrono::{Local, TimeDelta, Utc};
+
+use wifitify::db::models::*;
+use wifitify::db::Connection;
+
+/// Pause the collector for the given duration.
+pub async fn pause(connection: &mut Connection, duration: TimeDelta) -> Result<()> {
+    let pause = Pause {
+        created_at: Utc::now(),
+        until: Utc::now() + duration,
+    };
+    pause.persist(connection).await?;
+
+    println!(
+        "Paused until {}",
+        pause
+            .until
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S")
+    );
+
+    Ok(())
+}
+
+/// End the current pause right away.
+pub async fn resume(connection: &mut Connection) -> Result<()> {
+    let pause = Pause {
+        created_at: Utc::now(),
+        until: Utc::now(),
+    };
+    pause.persist(connection).await?;
+
+    println!("Resumed");
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use anyhow::{bail, Result};
+
+use super::find_device;
+use wifitify::db::models::*;
+use wifitify::db::Connection;
+use wifitify::events::device_name;
+
+/// Print all devices that are waiting to be acknowledged.
+pub async fn print_quarantined(connection: &mut Connection) -> Result<()> {
+    let mut devices: Vec<Device> = Device::known_devices(connection)
+        .await?
+        .into_values()
+        .filter(|device| device.quarantined)
+        .collect();
+    if devices.is_empty() {
+        println!("No quarantined devices");
+        return Ok(());
+    }
+
+    devices.sort_by_key(|device| device.id);
+    for device in devices {
+        println!(
+            "{:<20} {}",
+            device_name(&device),
+            device.vendor.as_deref().unwrap_or("Unknown vendor")
+        );
+    }
+
+    Ok(())
+}
+
+/// Release a device from quarantine and either watch or ignore it from now on.
+pub async fn acknowledge(connection: &mut Connection, name: String, watch: bool) -> Result<()> {
+    let devices = Device::known_devices(connection).await?;
+    let mut device = find_device(devices.values(), &name)?.clone();
+
+    if !device.quarantined {
+        bail!("Device {} isn't quarantined", name);
+    }
+
+    device.acknowledge(connection, watch).await?;
+    if watch {
+        println!("Device {} is watched from now on", device_name(&device));
+    } else {
+        println!("Device {} is ignored from now on", device_name(&device));
+    }
+
+    Ok(())
+}
@@ -3,8 +3,11 @@ use std::io::prelude::*;
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
+use chrono::Weekday;
 use serde_derive::{Deserialize, Serialize};
 
+use crate::events::{Event, EventKind};
+
 /// All settings which are used by both, the client and the daemon
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Collector {
@@ -14,6 +17,11 @@ pub struct Collector {
     pub always_sweep: bool,
     /// If this is set to a channel, only this specific channel will be monitored.
     pub fixed_channel: Option<i32>,
+    /// The monitor interfaces to listen on, if none are given on the command line.
+    /// The first one watches the stations, all others split the channels and sweep them.
+    /// During sweeps, the first one only visits the channels none of the others support.
+    #[serde(default)]
+    pub interfaces: Vec<String>,
 
     /// The time between full channel sweeps in seconds
     pub time_between_sweeps: i64,
@@ -21,6 +29,470 @@ pub struct Collector {
     pub sweep_channel_switch_timeout: i64,
     /// The time between channel switches during normal mode in milliseconds
     pub channel_switch_timeout: i64,
+
+    /// Time windows during which the collector runs in a reduced mode.
+    #[serde(default)]
+    pub schedules: Vec<Schedule>,
+    /// An `.ics` calendar file with holidays, e.g. exported from a calendar application.
+    #[serde(default)]
+    pub holidays: Option<PathBuf>,
+}
+
+/// Something the collector doesn't do, while a schedule is active.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Restriction {
+    /// Don't sweep all channels for new stations.
+    NoSweeps,
+    /// Don't register devices we've never seen before.
+    NoNewDevices,
+    /// Don't run hooks and rules or send events to notifications, webhooks and MQTT.
+    NoOutput,
+}
+
+/// A time window during which the collector runs in a reduced mode, e.g. during the night.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Schedule {
+    pub name: String,
+    /// The start of the window (local time, `HH:MM`).
+    pub after: Option<String>,
+    /// The end of the window (local time, `HH:MM`).
+    /// If this is earlier than `after`, the window wraps around midnight.
+    pub before: Option<String>,
+    /// Only use the window on these weekdays (e.g. `["sat", "sun"]`).
+    #[serde(default)]
+    pub weekdays: Vec<Weekday>,
+    /// The schedule is active during the whole day on holidays.
+    #[serde(default)]
+    pub holidays: bool,
+    pub restrictions: Vec<Restriction>,
+    /// Publish a summary of everything that happened, once the window ends.
+    #[serde(default)]
+    pub summary: bool,
+}
+
+/// Settings that decide whether a device is considered to be present.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Presence {
+    /// The time in seconds after which a device, that hasn't been seen, is considered to have left.
+    pub absence_timeout: i64,
+    /// The time window in seconds, in which `arrival_frames` have to be seen for a device to arrive.
+    pub arrival_window: i64,
+    /// The minimum amount of frames, that confirm the arrival of an absent device.
+    pub arrival_frames: usize,
+    /// The time window in seconds, which is used to detect flapping devices.
+    pub flap_window: i64,
+    /// The amount of presence changes during the flap window, after which a device is unstable.
+    /// Changes of unstable devices aren't published, until the device settles down.
+    /// Set this to 0 to disable flap detection.
+    pub flap_threshold: usize,
+    /// The amount of frames in a row on another station, after which a device has roamed.
+    pub roaming_frames: usize,
+}
+
+impl Default for Presence {
+    fn default() -> Self {
+        Presence {
+            absence_timeout: 600,
+            arrival_window: 60,
+            arrival_frames: 3,
+            flap_window: 3600,
+            flap_threshold: 6,
+            roaming_frames: 3,
+        }
+    }
+}
+
+/// What happens with devices, that show up on a watched station for the first time.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NewDevicePolicy {
+    /// Start watching the device right away.
+    Watch,
+    /// Don't watch the device, until it has been acknowledged via the CLI.
+    Quarantine,
+}
+
+/// Settings for devices, that show up on a watched station for the first time.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NewDevices {
+    pub policy: NewDevicePolicy,
+    /// A Wireshark `manuf` file, which is used to look up the vendor of new devices.
+    /// E.g. `/usr/share/wireshark/manuf`.
+    pub vendor_file: Option<PathBuf>,
+}
+
+impl Default for NewDevices {
+    fn default() -> Self {
+        NewDevices {
+            policy: NewDevicePolicy::Watch,
+            vendor_file: None,
+        }
+    }
+}
+
+/// Decides which events something reacts to.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EventFilter {
+    /// The kind of event.
+    pub event: EventKind,
+    /// Only react to events of the device with this nickname.
+    pub device: Option<String>,
+    /// Only react to events of the station with this nickname, ssid or mac address.
+    pub station: Option<String>,
+    /// Only react to events of the group with this name.
+    pub group: Option<String>,
+}
+
+impl EventFilter {
+    /// Check whether an event passes this filter.
+    pub fn matches(&self, event: &Event) -> bool {
+        if self.event != event.kind() {
+            return false;
+        }
+
+        if let Some(nickname) = &self.device {
+            let device_nickname = event.device().and_then(|device| device.nickname.as_ref());
+            if device_nickname != Some(nickname) {
+                return false;
+            }
+        }
+
+        if let Some(name) = &self.station {
+            let station = match event.station() {
+                Some(station) => station,
+                None => return false,
+            };
+            if station.nickname.as_ref() != Some(name)
+                && station.ssid.as_ref() != Some(name)
+                && &station.mac_address.to_string() != name
+            {
+                return false;
+            }
+        }
+
+        if let Some(name) = &self.group {
+            if event.group().map(|group| &group.name) != Some(name) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// A shell command, that's executed whenever a matching event occurs.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Hook {
+    /// The name that's used in the execution log. Defaults to the command.
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub filter: EventFilter,
+    /// The command, which will be executed via `sh -c`.
+    pub command: String,
+    /// The time in seconds after which the command is killed.
+    /// Defaults to the global hook timeout.
+    pub timeout: Option<u64>,
+}
+
+/// A hook, whose command is only executed if all of its conditions are met.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Rule {
+    pub name: String,
+    #[serde(flatten)]
+    pub filter: EventFilter,
+    /// The command, which will be executed via `sh -c`.
+    pub command: String,
+    /// The time in seconds after which the command is killed.
+    /// Defaults to the global hook timeout.
+    pub timeout: Option<u64>,
+
+    /// Only fire after this time of the day (local time, `HH:MM`).
+    pub after: Option<String>,
+    /// Only fire before this time of the day (local time, `HH:MM`).
+    /// If this is earlier than `after`, the time window wraps around midnight.
+    pub before: Option<String>,
+    /// Only fire on these weekdays (e.g. `["mon", "fri"]`).
+    #[serde(default)]
+    pub weekdays: Vec<Weekday>,
+    /// Only fire, if all devices with these nicknames are currently present.
+    #[serde(default)]
+    pub present: Vec<String>,
+    /// Only fire, if none of the devices with these nicknames are currently present.
+    #[serde(default)]
+    pub absent: Vec<String>,
+    /// Wait this many seconds before firing.
+    /// The rule is only fired, if the device is still present (`joined`) or still gone (`left`).
+    /// The same goes for groups, e.g. a `group_left` rule only fires, if everybody is still gone.
+    pub delay: Option<i64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Hooks {
+    pub commands: Vec<Hook>,
+    /// The maximum amount of hook and rule commands, that run at the same time.
+    pub max_concurrent: usize,
+    /// The default time in seconds after which commands are killed.
+    pub timeout: u64,
+    /// The maximum amount of bytes of stdout/stderr, that's stored for each execution.
+    pub output_limit: usize,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Hooks {
+            commands: Vec::new(),
+            max_concurrent: 4,
+            timeout: 30,
+            output_limit: 4096,
+        }
+    }
+}
+
+/// A HTTP endpoint, to which events are sent as JSON via POST requests.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Webhook {
+    pub url: String,
+    /// Only send these events to the endpoint.
+    /// All events except for `sweep_progress` and `sweep_finished` are sent, if this is empty.
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Webhooks {
+    pub endpoints: Vec<Webhook>,
+    /// The amount of attempts, after which the delivery of an event is given up.
+    pub max_attempts: i32,
+    /// The time in seconds before the first retry. This doubles with each failed attempt.
+    pub retry_interval: i64,
+    /// The maximum time in seconds between two retries.
+    pub max_retry_interval: i64,
+    /// The timeout of a single request in seconds.
+    pub timeout: u64,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Webhooks {
+            endpoints: Vec::new(),
+            max_attempts: 20,
+            retry_interval: 5,
+            max_retry_interval: 3600,
+            timeout: 10,
+        }
+    }
+}
+
+/// The connection to a MQTT broker and the topics that're used to publish device information.
+///
+/// In all device topics, `{device}` is replaced by the nickname or mac address of the device.
+/// In all station topics, `{station}` is replaced by the nickname, ssid or mac address of the station.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Mqtt {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Whether device information should be published as retained messages.
+    pub retain: bool,
+    /// The daemon's own status (`online`/`offline`). This is also used as the last will.
+    pub status_topic: String,
+    /// The presence of a device (`home`/`not_home`).
+    pub presence_topic: String,
+    /// The last time a device has been seen.
+    pub last_seen_topic: String,
+    /// The amount of bytes a device transferred during the last minute.
+    pub traffic_topic: String,
+    /// The amount of devices that're currently online on a station.
+    pub station_devices_topic: String,
+    /// Whether a device is `active` or `idle`.
+    /// `{activity}` is replaced by the name of the activity threshold.
+    pub activity_topic: String,
+
+    /// Announce all watched devices and stations via Home Assistant's MQTT discovery.
+    pub discovery: bool,
+    /// The topic prefix Home Assistant listens on for discovery messages.
+    pub discovery_prefix: String,
+    /// The time in seconds between checks for newly (un)watched devices and stations.
+    pub discovery_interval: u64,
+}
+
+impl Default for Mqtt {
+    fn default() -> Self {
+        Mqtt {
+            host: "localhost".into(),
+            port: 1883,
+            client_id: "wifitify".into(),
+            username: None,
+            password: None,
+            retain: true,
+            status_topic: "wifitify/status".into(),
+            presence_topic: "wifitify/devices/{device}/presence".into(),
+            last_seen_topic: "wifitify/devices/{device}/last_seen".into(),
+            traffic_topic: "wifitify/devices/{device}/bytes_per_minute".into(),
+            station_devices_topic: "wifitify/stations/{station}/devices".into(),
+            activity_topic: "wifitify/devices/{device}/{activity}".into(),
+            discovery: false,
+            discovery_prefix: "homeassistant".into(),
+            discovery_interval: 60,
+        }
+    }
+}
+
+/// Where rendered notifications are written to.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationOutput {
+    Stdout,
+    /// Append each notification as a new line to this file.
+    File(PathBuf),
+    /// Send each notification via POST request to this url.
+    Http(String),
+}
+
+/// A user-defined template, that's rendered for every matching event.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Notification {
+    /// Only render the template for these events.
+    /// It's rendered for all events except for `sweep_progress` and `sweep_finished`, if this is empty.
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    /// A [minijinja](https://docs.rs/minijinja) template, which gets the serialized event as context.
+    /// E.g. `{{ device.nickname }} left {{ station.ssid }} at {{ time | local }}`.
+    pub template: String,
+    pub output: NotificationOutput,
+    /// The content type of http requests. Defaults to `text/plain`.
+    pub content_type: Option<String>,
+}
+
+/// Decides when a device is active or idle, based on its traffic per minute.
+///
+/// Traffic between `idle_below` and `active_above` neither counts as active nor as idle.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ActivityThreshold {
+    /// The name of the activity, e.g. `streaming`.
+    pub name: String,
+    /// Only track the device with this nickname or mac address. Defaults to all watched devices.
+    pub device: Option<String>,
+    /// A device becomes active, once it transferred more bytes per minute than this ...
+    pub active_above: i32,
+    /// ... for this many minutes in a row.
+    pub active_for: i64,
+    /// A device becomes idle, once it transferred less bytes per minute than this ...
+    pub idle_below: i32,
+    /// ... for this many minutes in a row.
+    pub idle_for: i64,
+}
+
+/// An area around a station, defined by the signal strength of devices.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Zone {
+    /// The name of the zone, e.g. `front door`.
+    pub name: String,
+    /// Only use the signal on the station with this nickname, ssid or mac address.
+    /// Defaults to all watched stations.
+    pub station: Option<String>,
+    /// Devices with a stronger signal (in dBm) are inside the zone, e.g. `-50`.
+    pub min_signal: i32,
+    /// Devices with a stronger signal (in dBm) are outside the zone.
+    /// This allows zones at some distance to the station.
+    pub max_signal: Option<i32>,
+}
+
+/// Settings for proximity zones, which are based on the signal strength of devices.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Proximity {
+    /// How much a new signal measurement changes the smoothed signal (between 0 and 1).
+    /// Lower values are smoother, but react slower.
+    pub smoothing: f64,
+    /// The signal (in dBm) has to be this much outside of a zone's bounds to leave the zone.
+    pub hysteresis: i32,
+    pub zones: Vec<Zone>,
+}
+
+impl Default for Proximity {
+    fn default() -> Self {
+        Proximity {
+            smoothing: 0.2,
+            hysteresis: 3,
+            zones: Vec::new(),
+        }
+    }
+}
+
+/// Settings for Rhai scripts, which react to events with custom logic.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Scripts {
+    /// The directory containing all `.rhai` scripts.
+    /// Defaults to `wifitify/scripts` in the config directory.
+    pub directory: Option<PathBuf>,
+    /// The maximum amount of operations per event and script, which stops endless loops.
+    pub max_operations: u64,
+    /// The timeout in seconds for shell commands and HTTP requests of scripts.
+    pub timeout: u64,
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        Scripts {
+            directory: None,
+            max_operations: 100_000,
+            timeout: 10,
+        }
+    }
+}
+
+/// Settings for recording the raw captured frames to pcapng files.
+/// Only the frames of the first interface are recorded.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Recording {
+    /// The directory containing all recordings.
+    pub directory: PathBuf,
+    /// Start a new file, once the current one is larger than this many megabytes.
+    pub max_file_size: u64,
+    /// Start a new file, once the current one is older than this many seconds.
+    pub max_file_age: u64,
+    /// Remove the oldest files, once there are more than this many.
+    pub max_files: usize,
+    /// Only record frames from or to watched stations.
+    pub watched_only: bool,
+}
+
+impl Default for Recording {
+    fn default() -> Self {
+        Recording {
+            directory: PathBuf::from("/var/lib/wifitify/recordings"),
+            max_file_size: 100,
+            max_file_age: 3600,
+            max_files: 24,
+            watched_only: false,
+        }
+    }
+}
+
+/// Settings for the Unix socket, which streams all events as JSON lines.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Socket {
+    /// The path of the socket, e.g. `/run/wifitify/events.sock`.
+    pub path: PathBuf,
+}
+
+/// A group of devices, e.g. all devices of a person or a whole household.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Group {
+    pub name: String,
+    /// The nicknames of all member devices.
+    pub devices: Vec<String>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -28,6 +500,32 @@ pub struct Config {
     /// The TCP hostname/ip address.
     pub database_url: String,
     pub collector: Collector,
+    #[serde(default)]
+    pub presence: Presence,
+    #[serde(default)]
+    pub new_devices: NewDevices,
+    #[serde(default)]
+    pub hooks: Hooks,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub activities: Vec<ActivityThreshold>,
+    #[serde(default)]
+    pub proximity: Proximity,
+    #[serde(default)]
+    pub scripts: Scripts,
+    #[serde(default)]
+    pub webhooks: Webhooks,
+    #[serde(default)]
+    pub notifications: Vec<Notification>,
+    /// MQTT is only used, if this section exists.
+    pub mqtt: Option<Mqtt>,
+    /// The event socket is only created, if this section exists.
+    pub socket: Option<Socket>,
+    /// Frames are only recorded, if this section exists.
+    pub recording: Option<Recording>,
 }
 
 impl Config {
@@ -53,10 +551,26 @@ impl Config {
                 sweep_on_startup: true,
                 always_sweep: false,
                 fixed_channel: None,
+                interfaces: Vec::new(),
                 time_between_sweeps: 7200,
                 sweep_channel_switch_timeout: 5000,
                 channel_switch_timeout: 250,
+                schedules: Vec::new(),
+                holidays: None,
             },
+            presence: Presence::default(),
+            new_devices: NewDevices::default(),
+            hooks: Hooks::default(),
+            groups: Vec::new(),
+            rules: Vec::new(),
+            activities: Vec::new(),
+            proximity: Proximity::default(),
+            scripts: Scripts::default(),
+            webhooks: Webhooks::default(),
+            notifications: Vec::new(),
+            mqtt: None,
+            socket: None,
+            recording: None,
         };
         default_config.write()?;
 
@@ -84,4 +598,14 @@ impl Config {
         let config_dir = dirs::config_dir().context("Couldn't find config dir")?;
         Ok(config_dir.join("wifitify.toml"))
     }
+
+    /// Get the directory with all scripts, which is next to the config file by default.
+    pub fn get_script_dir(&self) -> Result<PathBuf> {
+        if let Some(directory) = &self.scripts.directory {
+            return Ok(directory.clone());
+        }
+
+        let config_dir = dirs::config_dir().context("Couldn't find config dir")?;
+        Ok(config_dir.join("wifitify").join("scripts"))
+    }
 }
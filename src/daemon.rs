@@ -0,0 +1,584 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use crossbeam_channel::{unbounded, RecvTimeoutError, Sender};
+use libwifi::Frame;
+use log::{debug, info};
+use radiotap::Radiotap;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::config::{Config, Restriction};
+use crate::data::{self, Observation, PacketContext};
+use crate::db::{self, DbPool};
+use crate::device::{supported_channels, switch_channel};
+use crate::events::Event;
+use crate::scripts::{spawn_script_runner, ScriptRunner};
+use crate::sink::{spawn_sink, EventSink};
+use crate::state::AppState;
+use crate::supervisor::Supervisor;
+use crate::sweeper::Sweeper;
+use crate::vendor::VendorDatabase;
+use crate::wifi::capture::watch_list_filter;
+use crate::wifi::recorder::{Recorder, SharedRecorder, WatchedStations};
+use crate::{event_log, hooks, listener, mqtt, notifications, quiet, roaming, socket, webhooks};
+
+/// Where the collector gets its wifi frames from.
+pub enum Source {
+    /// Listen on wifi devices in monitor mode, e.g. `wlan0`.
+    /// The first device watches the stations, all others split the channels and sweep them.
+    /// If no devices are given, the interfaces of the config are used.
+    Devices(Vec<String>),
+    /// Replay a recorded radiotap capture (pcap or pcapng).
+    /// Frames are replayed as fast as possible, unless `realtime` is set.
+    File { path: PathBuf, realtime: bool },
+}
+
+/// The collector, which listens for wifi frames and publishes events about devices and stations.
+///
+/// Applications can embed the collector and receive its events in-process,
+/// either via an [EventSink] or by subscribing to the events directly.
+pub struct Daemon {
+    state: AppState,
+    pool: DbPool,
+    subscribers: Subscribers,
+}
+
+/// The tasks of all consumers of our events.
+/// At the end of a replay, they're awaited, so they can handle all remaining events.
+#[derive(Default)]
+struct Subscribers {
+    tasks: Vec<JoinHandle<()>>,
+    /// The socket listener keeps the event bus around, to subscribe new clients.
+    socket: Option<JoinHandle<()>>,
+    scripts: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Daemon {
+    /// Connect to the database and prepare everything, that's needed to run the collector.
+    pub async fn new(config: Config) -> Result<Self> {
+        let pool: DbPool = db::init_pool(&config.database_url).await?;
+
+        // The supervisor executes all hook and rule commands.
+        let supervisor = Supervisor::new(&config.hooks, pool.clone())?;
+
+        let state = AppState::new(config, supervisor)?;
+
+        Ok(Daemon {
+            state,
+            pool,
+            subscribers: Subscribers::default(),
+        })
+    }
+
+    /// The database pool, which is shared with all parts of the collector.
+    pub fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+
+    /// Get a receiver for all events, that're published once the collector runs.
+    /// Just like hooks, the receiver doesn't get any events during quiet windows without output.
+    /// This includes the diagnostic sweep events, which can be skipped via [crate::events::EventKind::is_diagnostic].
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.state.events.subscribe()
+    }
+
+    /// Pass all events to a sink, once the collector runs.
+    /// Just like hooks, sinks don't get any events during quiet windows without output.
+    /// This includes the diagnostic sweep events, which can be skipped via [crate::events::EventKind::is_diagnostic].
+    pub fn add_sink(&mut self, sink: impl EventSink) {
+        let task = spawn_sink(sink, self.state.events.subscribe());
+        self.subscribers.tasks.push(task);
+    }
+
+    /// Listen for wifi frames and publish events until an error occurs.
+    /// Replays stop, once all frames of the capture file have been handled.
+    pub async fn run(self, source: Source) -> Result<()> {
+        let Daemon {
+            mut state,
+            mut pool,
+            mut subscribers,
+        } = self;
+
+        // Initialize the channel used to send Wifi frames from the receiver thread.
+        // Spawn the packet receiver thread afterwards.
+        let (sender, receiver) = unbounded::<(Frame, Radiotap, DateTime<Utc>)>();
+        // Frames of the additional interfaces are always handled as part of a sweep.
+        let (sweep_sender, sweep_receiver) = unbounded::<(Frame, Radiotap, DateTime<Utc>)>();
+        // The listeners only let beacons and frames of watched stations through.
+        // Their filters are updated, whenever the watched stations change.
+        let mut filter_senders = Vec::new();
+        let watched_stations = WatchedStations::default();
+        let mut sweepers = Vec::new();
+        let mut recorder: Option<SharedRecorder> = None;
+        // Replays don't have a device, which is why channels are never switched.
+        let device = match &source {
+            Source::Devices(devices) => {
+                let mut devices = devices.clone();
+                if devices.is_empty() {
+                    devices = state.config.collector.interfaces.clone();
+                }
+                if devices.is_empty() {
+                    bail!(
+                        "No device given. Pass one or set the interfaces of the collector config."
+                    );
+                }
+                let device = devices.remove(0);
+
+                // Raw frames can be recorded as evidence, in case presence decisions look wrong.
+                // Only the frames of the first device are recorded.
+                if let Some(config) = &state.config.recording {
+                    let recording = Recorder::new(config, watched_stations.clone())?;
+                    recorder = Some(Arc::new(Mutex::new(recording)));
+                }
+                let (filter_sender, filter_receiver) = unbounded::<String>();
+                listener::init_packet_listener_thread(
+                    &device,
+                    recorder.clone(),
+                    filter_receiver,
+                    sender,
+                )?;
+                filter_senders.push(filter_sender);
+
+                for sweeper in devices {
+                    let (filter_sender, filter_receiver) = unbounded::<String>();
+                    listener::init_packet_listener_thread(
+                        &sweeper,
+                        None,
+                        filter_receiver,
+                        sweep_sender.clone(),
+                    )?;
+                    filter_senders.push(filter_sender);
+                    sweepers.push(Sweeper::new(sweeper));
+                }
+
+                Some(device)
+            }
+            Source::File { path, realtime } => {
+                listener::init_file_reader_thread(path, *realtime, sender)?;
+                None
+            }
+        };
+        let device = device.as_deref();
+
+        // The packet handlers report everything they observe back to us via this channel.
+        let (observation_sender, observation_receiver) = unbounded::<Observation>();
+
+        // Everything the packet handlers need to know about new devices.
+        let vendors = match &state.config.new_devices.vendor_file {
+            Some(path) => VendorDatabase::load(path)?,
+            None => VendorDatabase::default(),
+        };
+        let packet_context = Arc::new(PacketContext {
+            new_device_policy: state.config.new_devices.policy,
+            vendors,
+            register_new_devices: AtomicBool::new(true),
+            watched_ssids: RwLock::new(HashMap::new()),
+            rogue_sightings: Mutex::new(HashMap::new()),
+            watched_devices: Mutex::new(HashSet::new()),
+        });
+
+        // Pauses are requested via the CLI and stored in the database.
+        let paused_until = quiet::spawn_pause_watcher(pool.clone());
+
+        // Start all consumers of our events.
+        subscribers.tasks.extend(hooks::spawn_hook_runner(
+            state.config.hooks.clone(),
+            state.supervisor.clone(),
+            state.events.subscribe(),
+        ));
+        subscribers.tasks.push(event_log::spawn_event_log(
+            pool.clone(),
+            state.events.subscribe_unmuted(),
+        ));
+        subscribers.tasks.push(roaming::spawn_attachment_log(
+            pool.clone(),
+            state.events.subscribe_unmuted(),
+        ));
+        subscribers.tasks.extend(notifications::spawn_notifier(
+            state.config.notifications.clone(),
+            state.events.subscribe(),
+        )?);
+        subscribers.tasks.extend(webhooks::spawn_webhook_delivery(
+            state.config.webhooks.clone(),
+            pool.clone(),
+            state.events.subscribe(),
+        ));
+        if let Some(mqtt) = &state.config.mqtt {
+            let client = mqtt::connect(mqtt);
+            subscribers.tasks.push(mqtt::spawn_mqtt_publisher(
+                mqtt.clone(),
+                client.clone(),
+                state.events.subscribe(),
+            ));
+            if mqtt.discovery {
+                mqtt::discovery::spawn_discovery(mqtt.clone(), client, pool.clone());
+            }
+        }
+        if let Some(socket) = &state.config.socket {
+            subscribers.socket = Some(socket::spawn_event_socket(socket, state.events.clone())?);
+        }
+
+        // All supported channel of this device.
+        let device_channels = match device {
+            Some(device) => supported_channels(device)?,
+            None => Vec::new(),
+        };
+        info!("Found supported channels: {:?}", device_channels);
+
+        // The additional interfaces split all channels they support between each other.
+        // The first device only sweeps the remaining ones, so it rarely leaves the watched channels.
+        let mut sweeper_channels = Vec::new();
+        for sweeper in sweepers.iter() {
+            let channels = supported_channels(&sweeper.device)?;
+            info!(
+                "Found supported channels of {}: {:?}",
+                sweeper.device, channels
+            );
+            sweeper_channels.push(channels);
+        }
+        Sweeper::split_channels(&mut sweepers, &sweeper_channels);
+        let sweep_channels: Vec<i32> = device_channels
+            .iter()
+            .filter(|channel| {
+                !sweeper_channels
+                    .iter()
+                    .flatten()
+                    .any(|other| other == *channel)
+            })
+            .copied()
+            .collect();
+        // The iterator that's used to walk through the channels during a sweep.
+        let mut sweep_channel_iter = sweep_channels.iter();
+        // Whether the first device is through with its channels of the current sweep.
+        let mut device_swept = sweep_channels.is_empty();
+        let sweep_total = sweep_channels.len() + sweepers.iter().map(Sweeper::total).sum::<usize>();
+        let sweep_channel_switch_timeout =
+            TimeDelta::try_milliseconds(state.config.collector.sweep_channel_switch_timeout)
+                .context("Invalid sweep_channel_switch_timeout")?;
+
+        // Load all devices and stations from the database.
+        // While doing so, we also determine, which channels should be watched depending on the watched
+        // stations we get from the database.
+        state.init_state(&mut pool, &device_channels).await?;
+        packet_context.update_watched_stations(&state.stations, &state.station_device_map);
+        let mut watched_bssids = None;
+        update_watch_list(
+            &state,
+            &mut watched_bssids,
+            &filter_senders,
+            &watched_stations,
+        );
+
+        if let Some(runner) = ScriptRunner::load(&state.config)? {
+            subscribers.scripts = Some(spawn_script_runner(
+                runner,
+                pool.clone(),
+                state.supervisor.clone(),
+                state.events.subscribe(),
+            ));
+        }
+
+        let shutdown = spawn_shutdown_listener()?;
+
+        // Replays are timed by the capture timestamps of their frames instead of the wall clock.
+        let mut replay_time: Option<DateTime<Utc>> = None;
+
+        loop {
+            if shutdown.load(Ordering::Relaxed) {
+                info!("Shutting down");
+                if let Some(Ok(mut recorder)) = recorder.as_ref().map(|recorder| recorder.lock()) {
+                    recorder.flush();
+                }
+                return Ok(());
+            }
+
+            // Apply the restrictions of the current quiet window, if there is one.
+            let pause = *paused_until.borrow();
+            state.update_quiet_hours(replay_time.unwrap_or_else(Utc::now), pause);
+            packet_context.register_new_devices.store(
+                !state.quiet.restricts(Restriction::NoNewDevices),
+                Ordering::Relaxed,
+            );
+
+            let sweep_due = state.should_sweep();
+            let doing_sweep = device.is_some() && sweep_due && !device_swept;
+            let mut replay_finished = false;
+            // Try to receive for a few milliseconds.
+            // Sometimes we might walk over channels that don't have any active devices.
+            // If we would keep listening on those devices, we would be wait forever!
+            match receiver.recv_timeout(std::time::Duration::from_millis(250)) {
+                Ok((frame, radiotap, time)) => {
+                    if device.is_none() {
+                        replay_time = replay_time.max(Some(time));
+                    }
+                    let handler = spawn_packet_handler(
+                        &pool,
+                        &packet_context,
+                        &observation_sender,
+                        (frame, radiotap, time),
+                        doing_sweep,
+                    );
+
+                    // Replayed frames are handled one after another to keep replays reproducible.
+                    if device.is_none() {
+                        let _ = handler.await;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => {
+                    if device.is_some() {
+                        bail!("The mpsc channel to the receiver thread disconnected.")
+                    }
+                    replay_finished = true;
+                }
+            }
+
+            // Frames of the additional interfaces queue up while we wait for the first device.
+            for packet in sweep_receiver.try_iter() {
+                spawn_packet_handler(&pool, &packet_context, &observation_sender, packet, true);
+            }
+
+            // Handle everything the packet handlers have seen since the last iteration.
+            // Device activity is fed into the presence tracker, which decides whether devices
+            // joined a station. Afterwards, check which devices haven't been seen for too long.
+            for observation in observation_receiver.try_iter() {
+                match observation {
+                    Observation::Activity(activity) => {
+                        if let Some(event) = state.traffic.handle_activity(&activity) {
+                            state.publish(event);
+                        }
+                        let zone_events = state.zones.handle_activity(&activity);
+                        let roaming_event = state.roaming.handle_activity(&activity);
+                        if let Some(event) = state.presence.handle_activity(activity) {
+                            state.publish(event);
+                        }
+                        if let Some(event) = roaming_event {
+                            state.publish(event);
+                        }
+                        for event in zone_events {
+                            state.publish(event);
+                        }
+                    }
+                    Observation::Event(event) => state.publish(event),
+                }
+            }
+            let now = replay_time.unwrap_or_else(Utc::now);
+            for event in state.presence.check_timeouts(now) {
+                state.publish(event);
+            }
+            for event in state.traffic.flush(now) {
+                state.publish(event);
+            }
+            for event in state.activity.check(now) {
+                state.publish(event);
+            }
+            let muted = state.events.is_muted();
+            state
+                .rules
+                .check_pending(now, muted, &state.presence, &state.groups);
+
+            let device = match device {
+                Some(device) => device,
+                None if replay_finished => {
+                    // The last minute of the capture is over as well.
+                    for event in state
+                        .traffic
+                        .flush(now + TimeDelta::try_minutes(1).unwrap())
+                    {
+                        state.publish(event);
+                    }
+                    info!("Replay finished, waiting for all events to be handled");
+                    subscribers.drain(state).await;
+                    return Ok(());
+                }
+                None => continue,
+            };
+
+            // The additional interfaces sweep their channels at the same time as the first one.
+            if sweep_due {
+                let now = Utc::now();
+                let device_done = if device_swept {
+                    sweep_channels.len()
+                } else {
+                    sweep_channels.len() - sweep_channel_iter.len()
+                };
+                let mut switched = Vec::new();
+                for sweeper in sweepers.iter_mut() {
+                    if let Some(channel) = sweeper.next_channel(now, sweep_channel_switch_timeout) {
+                        switch_channel(&sweeper.device, channel)?;
+                        debug!("Switching {} to channel {}", sweeper.device, channel);
+                        switched.push(channel);
+                    }
+                }
+
+                let done = device_done + sweepers.iter().map(Sweeper::done).sum::<usize>();
+                for channel in switched {
+                    state.publish(Event::SweepProgress {
+                        time: now,
+                        channel,
+                        done,
+                        total: sweep_total,
+                    });
+                }
+            }
+
+            // The sweep is over, once all interfaces visited all of their channels.
+            if sweep_due && device_swept && Sweeper::sweep_finished(&mut sweepers) {
+                device_swept = sweep_channels.is_empty();
+                state.reload_stations(&pool).await?;
+                packet_context.update_watched_stations(&state.stations, &state.station_device_map);
+                update_watch_list(
+                    &state,
+                    &mut watched_bssids,
+                    &filter_senders,
+                    &watched_stations,
+                );
+                state.update_watched_channels(&device_channels);
+                state.publish(Event::SweepFinished {
+                    time: Utc::now(),
+                    watched_channels: state.watched_channels.clone(),
+                });
+
+                if state.config.collector.always_sweep {
+                    state.schedule_sweep()
+                } else {
+                    state.last_full_sweep = Utc::now();
+                }
+                continue;
+            }
+
+            // Check whether we're currently doing a full sweep.
+            // If we aren't, cycle through all watched channels.
+            if !doing_sweep {
+                if state.should_switch_channel(false) {
+                    if let Some(channel) = state.get_next_watched_channel() {
+                        switch_channel(device, channel)?;
+                        debug!("Switching to channel {}", channel);
+                        state.last_channel_switch = Utc::now();
+                    }
+                }
+
+                continue;
+            }
+            // Check whether we should switch the channel right now. Otherwise, just continue.
+            if !state.should_switch_channel(true) {
+                continue;
+            }
+
+            // Check if there's another channel we should check.
+            // If that's not the case, the first device is done and returns to the watched channels.
+            let next_channel = if let Some(next_channel) = sweep_channel_iter.next() {
+                *next_channel
+            } else {
+                sweep_channel_iter = sweep_channels.iter();
+                device_swept = true;
+                continue;
+            };
+
+            switch_channel(device, next_channel)?;
+            state.last_channel_switch = Utc::now();
+            state.publish(Event::SweepProgress {
+                time: Utc::now(),
+                channel: next_channel,
+                done: sweep_channels.len() - sweep_channel_iter.len()
+                    + sweepers.iter().map(Sweeper::done).sum::<usize>(),
+                total: sweep_total,
+            });
+        }
+    }
+}
+
+impl Subscribers {
+    /// Let all consumers handle the remaining events and wait until they're done.
+    /// Once the event bus is gone, they receive the remaining events and stop afterwards.
+    async fn drain(self, state: AppState) {
+        if let Some(socket) = self.socket {
+            socket.abort();
+            let _ = socket.await;
+        }
+        let supervisor = state.supervisor.clone();
+        drop(state);
+
+        for task in self.tasks {
+            let _ = task.await;
+        }
+        if let Some(scripts) = self.scripts {
+            let _ = tokio::task::spawn_blocking(move || scripts.join()).await;
+        }
+
+        // Hooks and rules run their commands in the background.
+        supervisor.wait_idle().await;
+    }
+}
+
+/// Listen for SIGINT and SIGTERM.
+/// The returned flag is set once we should stop, which lets the main loop shut down gracefully.
+fn spawn_shutdown_listener() -> Result<Arc<AtomicBool>> {
+    let mut terminate = signal(SignalKind::terminate()).context("Couldn't listen for SIGTERM")?;
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    let flag = shutdown.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = terminate.recv() => (),
+        }
+        flag.store(true, Ordering::Relaxed);
+    });
+
+    Ok(shutdown)
+}
+
+/// Handle a captured frame in its own task.
+fn spawn_packet_handler(
+    pool: &DbPool,
+    context: &Arc<PacketContext>,
+    observation_sender: &Sender<Observation>,
+    (frame, radiotap, time): (Frame, Radiotap, DateTime<Utc>),
+    doing_sweep: bool,
+) -> JoinHandle<()> {
+    let pool = pool.clone();
+    let context = context.clone();
+    let observation_sender = observation_sender.clone();
+    tokio::spawn(async move {
+        data::handle_packet(
+            pool,
+            context,
+            frame,
+            radiotap,
+            time,
+            doing_sweep,
+            observation_sender,
+        )
+        .await;
+    })
+}
+
+/// Let the listener threads and the recorder know about changes of the watched stations.
+fn update_watch_list(
+    state: &AppState,
+    current: &mut Option<BTreeSet<String>>,
+    filter_senders: &[Sender<String>],
+    watched_stations: &WatchedStations,
+) {
+    let bssids = state.watched_bssids();
+    if current.as_ref() == Some(&bssids) {
+        return;
+    }
+
+    // Replays don't have any listener threads, in which case nobody receives the filter.
+    let filter = watch_list_filter(&bssids);
+    for filter_sender in filter_senders {
+        let _ = filter_sender.send(filter.clone());
+    }
+    if let Ok(mut watched_stations) = watched_stations.write() {
+        *watched_stations = bssids.iter().cloned().collect();
+    }
+    *current = Some(bssids);
+}
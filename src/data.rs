@@ -1,16 +1,117 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
 use anyhow::Result;
-use chrono::{Timelike, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
+use crossbeam_channel::Sender;
 use libwifi::frame::components::MacAddress;
 use libwifi::frame::BlockAckInfo;
 use libwifi::{Addresses, Frame};
 use log::{debug, info, warn};
 use radiotap::Radiotap;
 
+use crate::config::NewDevicePolicy;
 use crate::db::models::*;
 use crate::db::{Connection, DbPool};
 use crate::device::get_mhz_to_channel;
+use crate::events::Event;
+use crate::presence::Activity;
+use crate::traffic::get_minute;
+use crate::vendor::VendorDatabase;
+
+/// The privacy bit of the capability information in beacons.
+/// It's set, if the station requires encryption.
+const CAPABILITY_PRIVACY: u16 = 0x0010;
+
+/// Rogue access points are reported again, if they haven't been seen for this many seconds.
+/// Their sightings are only recorded this often as well.
+const ROGUE_REPORT_INTERVAL: i64 = 3600;
+
+/// Everything noteworthy the packet handlers observe is sent back to the main thread.
+pub enum Observation {
+    /// A watched device has been active on a watched station.
+    Activity(Activity),
+    /// Something happened, that should be published right away.
+    Event(Event),
+}
+
+/// Everything the packet handlers need to know, apart from the database.
+pub struct PacketContext {
+    pub new_device_policy: NewDevicePolicy,
+    pub vendors: VendorDatabase,
+    /// This is turned off by the main loop during quiet windows.
+    pub register_new_devices: AtomicBool,
+    /// All watched stations by their ssid, which are used to detect evil twins.
+    /// These are updated by the main loop, whenever the stations have been reloaded.
+    pub watched_ssids: RwLock<HashMap<String, Vec<Station>>>,
+    /// The last time each rogue access point `(bssid, station id)` has been recorded.
+    pub rogue_sightings: Mutex<HashMap<(String, i32), DateTime<Utc>>>,
+    /// The ids of all devices, that have been seen on any watched station.
+    /// Devices, that aren't in here yet, are new to us.
+    pub watched_devices: Mutex<HashSet<i32>>,
+}
+
+impl PacketContext {
+    /// Update the watched stations after the station cache has been reloaded.
+    /// Devices of stations, that became watched in the meantime, aren't new to us either.
+    pub fn update_watched_stations(
+        &self,
+        stations: &HashMap<String, Station>,
+        station_device_map: &HashMap<i32, HashSet<i32>>,
+    ) {
+        let mut watched_ssids: HashMap<String, Vec<Station>> = HashMap::new();
+        for station in stations.values().filter(|station| station.watch) {
+            if let Some(ssid) = station.ssid.as_ref().filter(|ssid| !ssid.is_empty()) {
+                watched_ssids
+                    .entry(ssid.clone())
+                    .or_default()
+                    .push(station.clone());
+            }
+        }
+
+        if let Ok(mut current) = self.watched_ssids.write() {
+            *current = watched_ssids;
+        }
+
+        if let Ok(mut watched_devices) = self.watched_devices.lock() {
+            watched_devices.extend(
+                stations
+                    .values()
+                    .filter(|station| station.watch)
+                    .filter_map(|station| station_device_map.get(&station.id))
+                    .flatten(),
+            );
+        }
+    }
+
+    /// Check whether the device has been seen on any watched station.
+    fn knows_watched_device(&self, device_id: i32) -> bool {
+        self.watched_devices
+            .lock()
+            .map(|devices| devices.contains(&device_id))
+            .unwrap_or(true)
+    }
 
-pub async fn handle_packet(pool: DbPool, frame: Frame, radiotap: Radiotap, doing_sweep: bool) {
+    /// Remember, that the device has been seen on a watched station.
+    /// Returns `false`, if another packet handler has been faster, i.e. it's no longer new.
+    fn claim_watched_device(&self, device_id: i32) -> bool {
+        self.watched_devices
+            .lock()
+            .map(|mut devices| devices.insert(device_id))
+            .unwrap_or(false)
+    }
+}
+
+pub async fn handle_packet(
+    pool: DbPool,
+    context: Arc<PacketContext>,
+    frame: Frame,
+    radiotap: Radiotap,
+    time: DateTime<Utc>,
+    doing_sweep: bool,
+    observation_sender: Sender<Observation>,
+) {
     let tries: i32 = 3;
     let mut current_try: i32 = 0;
 
@@ -39,7 +140,16 @@ pub async fn handle_packet(pool: DbPool, frame: Frame, radiotap: Radiotap, doing
     }
     let mut connection = connection.unwrap();
 
-    let result = extract_data(&mut connection, frame, radiotap, doing_sweep).await;
+    let result = extract_data(
+        &mut connection,
+        &context,
+        frame,
+        radiotap,
+        time,
+        doing_sweep,
+        &observation_sender,
+    )
+    .await;
     if let Err(err) = result {
         println!("Got error while handling packet {err:?}")
     };
@@ -47,10 +157,15 @@ pub async fn handle_packet(pool: DbPool, frame: Frame, radiotap: Radiotap, doing
 
 async fn extract_data(
     connection: &mut Connection,
+    context: &PacketContext,
     frame: Frame,
     radiotap: Radiotap,
+    time: DateTime<Utc>,
     should_update: bool,
+    observation_sender: &Sender<Observation>,
 ) -> Result<()> {
+    let rssi = radiotap.antenna_signal.map(|signal| signal.value as i32);
+
     match frame {
         Frame::Beacon(frame) => {
             let station_mac = frame.src().unwrap().clone();
@@ -73,47 +188,151 @@ async fn extract_data(
                 return Ok(());
             };
 
+            let ssid = frame.station_info.ssid.clone();
+            let protected = frame.capability_info & CAPABILITY_PRIVACY != 0;
+
             // We already know this station
             // In case we're doing a full hannel sweep right now, update any station metadata.
-            if let Some(mut station) = Station::get_by_mac(connection, &station_mac_string).await? {
-                if should_update {
+            let station = if let Some(mut station) =
+                Station::get_by_mac(connection, &station_mac_string).await?
+            {
+                // Someone might spoof the bssid of a watched station, but without encryption.
+                // Don't update the metadata in that case, so the spoofed station keeps standing out.
+                if station.watch {
+                    if let Some(known) = station.protected.filter(|known| *known != protected) {
+                        let reason = format!(
+                            "Security changed from {} to {}",
+                            security_name(known),
+                            security_name(protected)
+                        );
+                        let rogue = RogueAccessPoint {
+                            bssid: station_mac_string,
+                            station: station.id,
+                            ssid,
+                            reason,
+                            last_seen: time,
+                        };
+                        report_rogue(
+                            connection,
+                            context,
+                            observation_sender,
+                            rogue,
+                            &station,
+                            channel,
+                            rssi,
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+                }
+
+                if should_update || station.protected.is_none() {
                     station.channel = channel;
-                    station.ssid = frame.station_info.ssid.clone();
-                    station.power_level = radiotap.antenna_signal.map(|a| a.value as i32);
+                    station.ssid = ssid.clone();
+                    station.power_level = rssi;
+                    station.protected = Some(protected);
                     station.update_metadata(connection).await?;
                 }
-                return Ok(());
-            }
-            // Add the station to the database
-            let mut station = Station {
-                id: 0,
-                mac_address: station_mac.into(),
-                ssid: frame.station_info.ssid.clone(),
-                channel,
-                power_level: radiotap.antenna_signal.map(|a| a.value as i32),
-                nickname: None,
-                description: None,
-                watch: false,
+                station
+            } else {
+                // Add the station to the database
+                let mut station = Station {
+                    id: 0,
+                    mac_address: station_mac.into(),
+                    ssid: ssid.clone(),
+                    channel,
+                    power_level: rssi,
+                    nickname: None,
+                    description: None,
+                    watch: false,
+                    protected: Some(protected),
+                };
+                station.persist(connection).await?;
+
+                info!(
+                    "Found station {} with ssid: {:?}",
+                    station_mac_string,
+                    ssid.clone()
+                );
+
+                let _ = observation_sender.send(Observation::Event(Event::NewStation {
+                    station: station.clone(),
+                    time,
+                }));
+                station
             };
-            station.persist(connection).await?;
 
-            info!(
-                "Found station {} with ssid: {:?}",
-                station_mac_string,
-                frame.station_info.ssid.clone()
-            );
+            // An unwatched station, that uses the ssid of a watched one, might be an evil twin.
+            // Stations that legitimately share an ssid (e.g. mesh networks) need to be watched.
+            match ssid {
+                Some(ssid) if !station.watch && !ssid.is_empty() => {
+                    let watched_stations = match context.watched_ssids.read() {
+                        Ok(watched_ssids) => watched_ssids.get(&ssid).cloned().unwrap_or_default(),
+                        Err(_) => Vec::new(),
+                    };
+                    for watched in watched_stations {
+                        let reason = match watched.protected {
+                            Some(known) if known != protected => format!(
+                                "Unknown bssid for ssid {} with different security ({} instead of {})",
+                                ssid,
+                                security_name(protected),
+                                security_name(known)
+                            ),
+                            _ => format!("Unknown bssid for ssid {}", ssid),
+                        };
+                        let rogue = RogueAccessPoint {
+                            bssid: station_mac_string.clone(),
+                            station: watched.id,
+                            ssid: Some(ssid.clone()),
+                            reason,
+                            last_seen: time,
+                        };
+                        report_rogue(
+                            connection,
+                            context,
+                            observation_sender,
+                            rogue,
+                            &watched,
+                            channel,
+                            rssi,
+                        )
+                        .await?;
+                    }
+                }
+                _ => (),
+            }
         }
         Frame::Data(frame) => {
             let src = frame.src().expect("Data frames always have a source");
             let dest = frame.dest();
 
-            log_data_frame(connection, src, dest, frame.data.len() as i32).await?;
+            log_data_frame(
+                connection,
+                context,
+                observation_sender,
+                src,
+                dest,
+                frame.data.len() as i32,
+                rssi,
+                time,
+            )
+            .await?;
         }
         Frame::QosData(frame) => {
             let src = frame.src().expect("Data frames always have a source");
             let dest = frame.dest();
 
-            log_data_frame(connection, src, dest, frame.data.len() as i32).await?;
+            log_data_frame(
+                connection,
+                context,
+                observation_sender,
+                src,
+                dest,
+                frame.data.len() as i32,
+                rssi,
+                time,
+            )
+            .await?;
         }
         Frame::BlockAck(frame) => {
             let src = frame
@@ -124,10 +343,30 @@ async fn extract_data(
 
             match frame.acks {
                 BlockAckInfo::Basic(_) => {
-                    log_data_frame(connection, &src, &dest, 100).await?;
+                    log_data_frame(
+                        connection,
+                        context,
+                        observation_sender,
+                        &src,
+                        &dest,
+                        100,
+                        rssi,
+                        time,
+                    )
+                    .await?;
                 }
                 BlockAckInfo::Compressed(acks) => {
-                    log_data_frame(connection, &src, &dest, (acks.len() * 500) as i32).await?;
+                    log_data_frame(
+                        connection,
+                        context,
+                        observation_sender,
+                        &src,
+                        &dest,
+                        (acks.len() * 500) as i32,
+                        rssi,
+                        time,
+                    )
+                    .await?;
                 }
             }
         }
@@ -137,11 +376,66 @@ async fn extract_data(
     Ok(())
 }
 
+/// Record a rogue access point and let everybody know about it.
+/// Each access point is recorded and reported at most once per report interval, since they
+/// send several beacons per second.
+async fn report_rogue(
+    connection: &mut Connection,
+    context: &PacketContext,
+    observation_sender: &Sender<Observation>,
+    rogue: RogueAccessPoint,
+    station: &Station,
+    channel: i32,
+    rssi: Option<i32>,
+) -> Result<()> {
+    let interval = TimeDelta::try_seconds(ROGUE_REPORT_INTERVAL).unwrap();
+
+    if let Ok(mut sightings) = context.rogue_sightings.lock() {
+        let key = (rogue.bssid.clone(), rogue.station);
+        match sightings.get(&key) {
+            Some(last) if rogue.last_seen - *last < interval => return Ok(()),
+            _ => {
+                sightings.insert(key, rogue.last_seen);
+            }
+        }
+    }
+
+    // The access point might have been reported shortly before a restart.
+    let previous = rogue.persist(connection).await?;
+    if previous.is_some_and(|previous| rogue.last_seen - previous < interval) {
+        return Ok(());
+    }
+
+    let _ = observation_sender.send(Observation::Event(Event::RogueAccessPoint {
+        station: station.clone(),
+        bssid: rogue.bssid,
+        ssid: rogue.ssid,
+        channel,
+        rssi,
+        time: rogue.last_seen,
+        reason: rogue.reason,
+    }));
+
+    Ok(())
+}
+
+fn security_name(protected: bool) -> &'static str {
+    if protected {
+        "protected"
+    } else {
+        "open"
+    }
+}
+
 async fn log_data_frame(
     connection: &mut Connection,
+    context: &PacketContext,
+    observation_sender: &Sender<Observation>,
     src: &MacAddress,
     dest: &MacAddress,
     data_length: i32,
+    rssi: Option<i32>,
+    captured: DateTime<Utc>,
 ) -> Result<()> {
     // Data frames can go in both directions.
     // Check if either src or dest is a known station, the other one has to be the device.
@@ -160,11 +454,14 @@ async fn log_data_frame(
         return Ok(());
     }
 
-    // Either get the device id from the known device map.
-    // If it's not in there yet, register a new client and add the client id to the map.
-    let device =
+    // Either get the device from the database or register a new device.
+    // Devices on other stations are watched, but we'll never track them anyway.
+    let mut device =
         if let Some(device) = Device::get_by_mac(connection, &device_mac.to_string()).await? {
             device
+        } else if !context.register_new_devices.load(Ordering::Relaxed) {
+            // New devices aren't registered during some quiet windows.
+            return Ok(());
         } else {
             let mut device = Device {
                 id: 0,
@@ -172,20 +469,50 @@ async fn log_data_frame(
                 nickname: None,
                 description: None,
                 watch: true,
+                vendor: context.vendors.lookup(&device_mac.to_string()),
+                quarantined: false,
             };
-
             device.id = device.persist(connection).await?;
             device
         };
 
+    // A device is new to us, once it shows up on one of our own stations for the first time.
+    // It might have been registered on another station (e.g. a neighbour's) before.
+    // Only those devices are subject to the policy and announced.
+    if station.watch && !context.knows_watched_device(device.id) {
+        if !context.register_new_devices.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        // Several packet handlers might see a new device at the same time.
+        // Only the first one announces it.
+        if context.claim_watched_device(device.id) {
+            if context.new_device_policy == NewDevicePolicy::Quarantine {
+                device.quarantine(connection).await?;
+            }
+            let device_station = DeviceStation {
+                station: station.id,
+                device: device.id,
+            };
+            device_station.persist(connection).await?;
+
+            let _ = observation_sender.send(Observation::Event(Event::NewDevice {
+                device: device.clone(),
+                station: station.clone(),
+                time: captured,
+                rssi,
+            }));
+        }
+    }
+
     // Only track activity on explitly watched stations and devices.
     if !(station.watch && device.watch) {
         return Ok(());
     }
 
-    let mut time = Utc::now();
-    time = time.with_second(0).unwrap();
-    time = time.with_nanosecond(0).unwrap();
+    // The traffic is bucketed by the time the frame has been captured.
+    // The packet handler might run quite a bit later under load or for replayed captures.
+    let time = get_minute(captured);
 
     let device_name = device
         .nickname
@@ -217,5 +544,19 @@ async fn log_data_frame(
         device_station.persist(connection).await?;
     }
 
+    // The signal strength is measured for the sender of the frame.
+    // Only frames sent by the device tell us something about the device's proximity.
+    let rssi = if device_mac == src { rssi } else { None };
+
+    // Let the presence tracker know, that we've just seen this device.
+    // This only errors if the main thread went away, in which case we're shutting down anyway.
+    let _ = observation_sender.send(Observation::Activity(Activity {
+        device,
+        station,
+        time: captured,
+        bytes: data_length,
+        rssi,
+    }));
+
     Ok(())
 }
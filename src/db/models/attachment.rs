@@ -0,0 +1,105 @@
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::types::chrono::DateTime;
+use sqlx::FromRow;
+
+use crate::db::Connection;
+
+/// The time span during which a device has been attached to a station.
+#[derive(FromRow)]
+pub struct Attachment {
+    pub id: i32,
+    pub device: i32,
+    pub station: i32,
+    pub attached_at: DateTime<Utc>,
+    /// This is `None`, as long as the device is still attached to the station.
+    pub detached_at: Option<DateTime<Utc>>,
+}
+
+impl Attachment {
+    /// Start a new attachment of a device to a station.
+    pub async fn attach(
+        connection: &mut Connection,
+        device: i32,
+        station: i32,
+        time: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query!(
+            "
+INSERT INTO device_attachments (device, station, attached_at)
+VALUES ($1, $2, $3)
+",
+            device,
+            station,
+            time,
+        )
+        .execute(&mut **connection)
+        .await?;
+
+        Ok(())
+    }
+
+    /// End the current attachment of a device to a station.
+    pub async fn detach(
+        connection: &mut Connection,
+        device: i32,
+        station: i32,
+        time: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query!(
+            "
+UPDATE device_attachments
+    SET detached_at = $3
+WHERE device = $1 AND station = $2 AND detached_at IS NULL
+",
+            device,
+            station,
+            time,
+        )
+        .execute(&mut **connection)
+        .await?;
+
+        Ok(())
+    }
+
+    /// End all attachments, that are still open.
+    /// This is used on startup, since the daemon doesn't know where devices went in the meantime.
+    pub async fn detach_all(connection: &mut Connection, time: DateTime<Utc>) -> Result<()> {
+        sqlx::query!(
+            "
+UPDATE device_attachments
+    SET detached_at = $1
+WHERE detached_at IS NULL
+",
+            time,
+        )
+        .execute(&mut **connection)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get all attachments, that didn't end before a given time.
+    /// Optionally only those of a single device.
+    pub async fn since(
+        connection: &mut Connection,
+        since: DateTime<Utc>,
+        device: Option<i32>,
+    ) -> Result<Vec<Attachment>> {
+        let attachments = sqlx::query_as!(
+            Attachment,
+            "
+SELECT *
+FROM device_attachments
+WHERE (detached_at IS NULL OR detached_at >= $1) AND ($2::integer IS NULL OR device = $2)
+ORDER BY attached_at
+",
+            since,
+            device,
+        )
+        .fetch_all(&mut **connection)
+        .await?;
+
+        Ok(attachments)
+    }
+}
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use serde_derive::Serialize;
 use sqlx::FromRow;
 
 use std::collections::HashMap;
@@ -6,13 +7,18 @@ use std::collections::HashMap;
 use crate::db::types::MacAddress;
 use crate::db::Connection;
 
-#[derive(FromRow)]
+#[derive(Clone, Debug, FromRow, Serialize)]
 pub struct Device {
     pub id: i32,
     pub mac_address: MacAddress,
     pub nickname: Option<String>,
     pub description: Option<String>,
     pub watch: bool,
+    /// The manufacturer, as determined by the OUI of the mac address.
+    pub vendor: Option<String>,
+    /// New devices may be quarantined until they're acknowledged.
+    /// Quarantined devices aren't watched.
+    pub quarantined: bool,
 }
 
 impl Device {
@@ -28,7 +34,9 @@ SELECT
     mac_address as "mac_address: MacAddress",
     nickname,
     description,
-    watch
+    watch,
+    vendor,
+    quarantined
 FROM devices
 WHERE mac_address = $1
 "#,
@@ -44,13 +52,16 @@ WHERE mac_address = $1
         let record = sqlx::query!(
             "
 INSERT INTO devices
-(mac_address, nickname, description)
-VALUES ($1, $2, $3)
+(mac_address, nickname, description, watch, vendor, quarantined)
+VALUES ($1, $2, $3, $4, $5, $6)
 RETURNING id
 ",
             self.mac_address.to_string(),
             self.nickname.clone(),
             self.description.clone(),
+            self.watch,
+            self.vendor.clone(),
+            self.quarantined,
         )
         .fetch_one(&mut **connection)
         .await?;
@@ -59,6 +70,45 @@ RETURNING id
         Ok(record.id)
     }
 
+    /// Release a device from quarantine and decide whether it should be watched.
+    pub async fn acknowledge(&mut self, connection: &mut Connection, watch: bool) -> Result<()> {
+        sqlx::query!(
+            "
+UPDATE devices
+    SET quarantined = FALSE,
+    watch = $2
+WHERE id = $1
+",
+            self.id,
+            watch,
+        )
+        .execute(&mut **connection)
+        .await?;
+
+        self.quarantined = false;
+        self.watch = watch;
+        Ok(())
+    }
+
+    /// Quarantine a device, which isn't watched until it's acknowledged.
+    pub async fn quarantine(&mut self, connection: &mut Connection) -> Result<()> {
+        sqlx::query!(
+            "
+UPDATE devices
+    SET quarantined = TRUE,
+    watch = FALSE
+WHERE id = $1
+",
+            self.id,
+        )
+        .execute(&mut **connection)
+        .await?;
+
+        self.quarantined = true;
+        self.watch = false;
+        Ok(())
+    }
+
     pub async fn known_devices(connection: &mut Connection) -> Result<HashMap<String, Device>> {
         let devices: Vec<Device> = sqlx::query_as!(
             Device,
@@ -68,7 +118,9 @@ SELECT
     mac_address as "mac_address: MacAddress",
     nickname,
     description,
-    watch
+    watch,
+    vendor,
+    quarantined
 FROM devices"#
         )
         .fetch_all(&mut **connection)
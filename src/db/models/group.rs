@@ -0,0 +1,56 @@
+use anyhow::Result;
+use serde_derive::Serialize;
+use sqlx::FromRow;
+
+use crate::db::Connection;
+
+/// A group of devices, e.g. all devices of a person or a whole household.
+#[derive(Clone, Debug, FromRow, Serialize)]
+pub struct Group {
+    pub id: i32,
+    pub name: String,
+}
+
+impl Group {
+    /// Get the group with the given name. It's created, if it doesn't exist yet.
+    pub async fn get_or_create(connection: &mut Connection, name: &str) -> Result<Group> {
+        let group = sqlx::query_as!(
+            Group,
+            "
+INSERT INTO device_groups (name)
+VALUES ($1)
+ON CONFLICT (name) DO
+UPDATE SET name = EXCLUDED.name
+RETURNING id, name
+",
+            name
+        )
+        .fetch_one(&mut **connection)
+        .await?;
+
+        Ok(group)
+    }
+
+    /// Replace all members of this group.
+    pub async fn set_members(&self, connection: &mut Connection, devices: &[i32]) -> Result<()> {
+        sqlx::query!(
+            "DELETE FROM device_groups_devices WHERE device_group = $1",
+            self.id
+        )
+        .execute(&mut **connection)
+        .await?;
+
+        sqlx::query!(
+            "
+INSERT INTO device_groups_devices (device_group, device)
+SELECT $1, * FROM UNNEST($2::integer[])
+",
+            self.id,
+            devices,
+        )
+        .execute(&mut **connection)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::types::chrono::DateTime;
+use sqlx::FromRow;
+
+use crate::db::Connection;
+
+/// The result of a single execution of a hook or rule command.
+#[derive(FromRow)]
+pub struct HookExecution {
+    pub id: i32,
+    /// The name of the hook or rule.
+    pub name: String,
+    pub command: String,
+    /// The kind of the event that triggered the execution.
+    pub event: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: i64,
+    /// This is `None`, if the command couldn't be started, was killed or timed out.
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    /// The (truncated) output of the command.
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl HookExecution {
+    pub async fn persist(&mut self, connection: &mut Connection) -> Result<i32> {
+        let record = sqlx::query!(
+            "
+INSERT INTO hook_executions
+(name, command, event, started_at, duration_ms, exit_code, timed_out, stdout, stderr)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+RETURNING id
+",
+            self.name,
+            self.command,
+            self.event,
+            self.started_at,
+            self.duration_ms,
+            self.exit_code,
+            self.timed_out,
+            self.stdout,
+            self.stderr,
+        )
+        .fetch_one(&mut **connection)
+        .await?;
+
+        self.id = record.id;
+        Ok(record.id)
+    }
+
+    /// Get the latest executions, newest first.
+    pub async fn latest(connection: &mut Connection, limit: i64) -> Result<Vec<HookExecution>> {
+        let executions = sqlx::query_as!(
+            HookExecution,
+            "
+SELECT *
+FROM hook_executions
+ORDER BY started_at DESC
+LIMIT $1
+",
+            limit
+        )
+        .fetch_all(&mut **connection)
+        .await?;
+
+        Ok(executions)
+    }
+}
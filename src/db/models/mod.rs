@@ -1,9 +1,23 @@
+mod attachment;
 mod data;
 mod device;
 mod device_station;
+mod group;
+mod hook_execution;
+mod pause;
+mod presence_event;
+mod rogue_access_point;
 mod station;
+mod webhook_delivery;
 
+pub use attachment::Attachment;
 pub use data::Data;
 pub use device::Device;
 pub use device_station::DeviceStation;
+pub use group::Group;
+pub use hook_execution::HookExecution;
+pub use pause::Pause;
+pub use presence_event::PresenceEvent;
+pub use rogue_access_point::RogueAccessPoint;
 pub use station::Station;
+pub use webhook_delivery::WebhookDelivery;
@@ -0,0 +1,45 @@
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::types::chrono::DateTime;
+
+use crate::db::Connection;
+
+/// A request to pause the collector, which has been made via the CLI.
+/// Only the latest pause is relevant, resuming is done by a pause that ends right away.
+pub struct Pause {
+    pub created_at: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+}
+
+impl Pause {
+    pub async fn persist(&self, connection: &mut Connection) -> Result<()> {
+        sqlx::query!(
+            "
+INSERT INTO collector_pauses (created_at, until)
+VALUES ($1, $2)
+",
+            self.created_at,
+            self.until,
+        )
+        .execute(&mut **connection)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the end of the latest pause, if there is one.
+    pub async fn latest_until(connection: &mut Connection) -> Result<Option<DateTime<Utc>>> {
+        let record = sqlx::query!(
+            "
+SELECT until
+FROM collector_pauses
+ORDER BY created_at DESC
+LIMIT 1
+"
+        )
+        .fetch_optional(&mut **connection)
+        .await?;
+
+        Ok(record.map(|record| record.until))
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::types::chrono::DateTime;
+use sqlx::FromRow;
+
+use crate::db::Connection;
+
+/// A single presence transition of a device on a station.
+#[derive(FromRow)]
+pub struct PresenceEvent {
+    pub time: DateTime<Utc>,
+    pub device: i32,
+    pub station: i32,
+    /// The kind of the event (e.g. `joined` or `left`).
+    pub kind: String,
+    /// Why the presence changed.
+    pub reason: String,
+}
+
+impl PresenceEvent {
+    pub async fn persist(&self, connection: &mut Connection) -> Result<()> {
+        sqlx::query!(
+            "
+INSERT INTO presence_events (time, device, station, kind, reason)
+VALUES ($1, $2, $3, $4, $5)
+",
+            self.time,
+            self.device,
+            self.station,
+            self.kind,
+            self.reason,
+        )
+        .execute(&mut **connection)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get all events since a given time, optionally only those of a single device.
+    pub async fn since(
+        connection: &mut Connection,
+        since: DateTime<Utc>,
+        device: Option<i32>,
+    ) -> Result<Vec<PresenceEvent>> {
+        let events = sqlx::query_as!(
+            PresenceEvent,
+            "
+SELECT *
+FROM presence_events
+WHERE time >= $1 AND ($2::integer IS NULL OR device = $2)
+ORDER BY time
+",
+            since,
+            device,
+        )
+        .fetch_all(&mut **connection)
+        .await?;
+
+        Ok(events)
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::types::chrono::DateTime;
+
+use crate::db::Connection;
+
+/// An access point, that impersonates one of our watched stations.
+pub struct RogueAccessPoint {
+    pub bssid: String,
+    /// The id of the watched station, that's being impersonated.
+    pub station: i32,
+    pub ssid: Option<String>,
+    /// Why the access point is considered to be rogue.
+    pub reason: String,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl RogueAccessPoint {
+    /// Record a sighting of this access point.
+    /// Returns the time of the previous sighting, if the access point has been seen before.
+    pub async fn persist(&self, connection: &mut Connection) -> Result<Option<DateTime<Utc>>> {
+        let record = sqlx::query!(
+            "
+WITH previous AS (
+    SELECT last_seen FROM rogue_access_points WHERE bssid = $1 AND station = $2
+)
+INSERT INTO rogue_access_points
+(bssid, station, ssid, reason, first_seen, last_seen)
+VALUES ($1, $2, $3, $4, $5, $5)
+ON CONFLICT (bssid, station) DO UPDATE
+    SET ssid = EXCLUDED.ssid,
+    reason = EXCLUDED.reason,
+    last_seen = EXCLUDED.last_seen
+RETURNING (SELECT last_seen FROM previous) as previous
+",
+            self.bssid,
+            self.station,
+            self.ssid,
+            self.reason,
+            self.last_seen,
+        )
+        .fetch_one(&mut **connection)
+        .await?;
+
+        Ok(record.previous)
+    }
+}
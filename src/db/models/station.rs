@@ -1,4 +1,5 @@
 use anyhow::Result;
+use serde_derive::Serialize;
 use sqlx::FromRow;
 
 use std::collections::HashMap;
@@ -6,7 +7,7 @@ use std::collections::HashMap;
 use crate::db::types::MacAddress;
 use crate::db::Connection;
 
-#[derive(FromRow)]
+#[derive(Clone, Debug, FromRow, Serialize)]
 pub struct Station {
     pub id: i32,
     pub mac_address: MacAddress,
@@ -16,6 +17,9 @@ pub struct Station {
     pub nickname: Option<String>,
     pub description: Option<String>,
     pub watch: bool,
+    /// Whether the station requires encryption.
+    /// This is `None`, until we've seen a beacon of the station.
+    pub protected: Option<bool>,
 }
 
 impl Station {
@@ -34,7 +38,8 @@ SELECT
     power_level,
     watch,
     nickname,
-    description
+    description,
+    protected
 FROM stations
 WHERE mac_address = $1
 "#,
@@ -50,8 +55,8 @@ WHERE mac_address = $1
         let record = sqlx::query!(
             "
 INSERT INTO stations
-(mac_address, ssid, nickname, description, channel)
-VALUES ($1, $2, $3, $4, $5)
+(mac_address, ssid, nickname, description, channel, protected)
+VALUES ($1, $2, $3, $4, $5, $6)
 RETURNING id
 ",
             self.mac_address.to_string(),
@@ -59,6 +64,7 @@ RETURNING id
             self.nickname.clone(),
             self.description.clone(),
             self.channel,
+            self.protected,
         )
         .fetch_one(&mut **connection)
         .await?;
@@ -73,13 +79,15 @@ RETURNING id
 UPDATE stations
     SET ssid = $2,
     channel = $3,
-    power_level = $4
+    power_level = $4,
+    protected = $5
 WHERE id = $1
 ",
             self.id,
             self.ssid.clone(),
             self.channel,
             self.power_level,
+            self.protected,
         )
         .execute(&mut **connection)
         .await?;
@@ -99,7 +107,8 @@ SELECT
     power_level,
     watch,
     nickname,
-    description
+    description,
+    protected
 FROM stations
 "#
         )
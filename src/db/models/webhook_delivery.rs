@@ -0,0 +1,89 @@
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::types::chrono::DateTime;
+use sqlx::FromRow;
+
+use crate::db::Connection;
+
+/// A webhook request, that still needs to be delivered.
+#[derive(FromRow)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub url: String,
+    /// The JSON body of the request.
+    pub payload: String,
+    /// The amount of failed delivery attempts so far.
+    pub attempts: i32,
+    pub next_attempt: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+impl WebhookDelivery {
+    pub async fn persist(&mut self, connection: &mut Connection) -> Result<i32> {
+        let record = sqlx::query!(
+            "
+INSERT INTO webhook_deliveries
+(url, payload, attempts, next_attempt, last_error)
+VALUES ($1, $2, $3, $4, $5)
+RETURNING id
+",
+            self.url,
+            self.payload,
+            self.attempts,
+            self.next_attempt,
+            self.last_error,
+        )
+        .fetch_one(&mut **connection)
+        .await?;
+
+        self.id = record.id;
+        Ok(record.id)
+    }
+
+    /// Get all deliveries, whose next attempt is due.
+    pub async fn due(connection: &mut Connection, now: DateTime<Utc>) -> Result<Vec<Self>> {
+        let deliveries = sqlx::query_as!(
+            WebhookDelivery,
+            "
+SELECT *
+FROM webhook_deliveries
+WHERE next_attempt <= $1
+ORDER BY next_attempt
+",
+            now
+        )
+        .fetch_all(&mut **connection)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    /// Save the result of a failed delivery attempt.
+    pub async fn update_attempt(&self, connection: &mut Connection) -> Result<()> {
+        sqlx::query!(
+            "
+UPDATE webhook_deliveries
+    SET attempts = $2,
+    next_attempt = $3,
+    last_error = $4
+WHERE id = $1
+",
+            self.id,
+            self.attempts,
+            self.next_attempt,
+            self.last_error,
+        )
+        .execute(&mut **connection)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, connection: &mut Connection) -> Result<()> {
+        sqlx::query!("DELETE FROM webhook_deliveries WHERE id = $1", self.id)
+            .execute(&mut **connection)
+            .await?;
+
+        Ok(())
+    }
+}
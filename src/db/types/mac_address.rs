@@ -1,10 +1,12 @@
 use anyhow::Result;
 use libwifi::frame::components::{MacAddress as LibWifiMacAddress, MacParseError};
+use serde::{Serialize, Serializer};
 use sqlx::{Database, Decode};
 
 use std::str::FromStr;
 
 /// New-type struct so we can implement the database decoder for libwifi's MacAddress struct.
+#[derive(Clone, Debug)]
 pub struct MacAddress(LibWifiMacAddress);
 
 impl std::ops::Deref for MacAddress {
@@ -45,3 +47,10 @@ where
         Ok(MacAddress::from_str(value)?)
     }
 }
+
+/// Serialize the MacAddress in its usual string representation.
+impl Serialize for MacAddress {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
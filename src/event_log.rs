@@ -0,0 +1,57 @@
+use anyhow::Result;
+use log::{error, warn};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::task::JoinHandle;
+
+use crate::db::models::PresenceEvent;
+use crate::db::DbPool;
+use crate::events::Event;
+
+/// Spawn the task that writes all presence transitions into the `presence_events` table.
+/// Devices becoming unstable or stable again are logged as well.
+pub fn spawn_event_log(pool: DbPool, mut receiver: Receiver<Event>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(count)) => {
+                    warn!("Event log couldn't keep up and missed {} events", count);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            if let Err(err) = log_event(&pool, &event).await {
+                error!("Failed to write presence event: {:?}", err);
+            }
+        }
+    })
+}
+
+async fn log_event(pool: &DbPool, event: &Event) -> Result<()> {
+    let reason = match event {
+        Event::DeviceJoined { reason, .. }
+        | Event::DeviceLeft { reason, .. }
+        | Event::DeviceUnstable { reason, .. }
+        | Event::DeviceStable { reason, .. } => reason.clone(),
+        _ => return Ok(()),
+    };
+    let device = event
+        .device()
+        .expect("Presence events always have a device");
+    let station = event
+        .station()
+        .expect("Presence events always have a station");
+
+    let presence_event = PresenceEvent {
+        time: event.time(),
+        device: device.id,
+        station: station.id,
+        kind: event.kind().as_str().to_string(),
+        reason,
+    };
+
+    let mut connection = pool.acquire().await?;
+    presence_event.persist(&mut connection).await
+}
@@ -0,0 +1,598 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use serde_derive::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::db::models::*;
+
+/// The amount of events a slow subscriber may lag behind, before it starts to miss events.
+const EVENT_BUFFER_SIZE: usize = 1024;
+
+/// All events that are emitted by the daemon.
+///
+/// Serialized events are tagged with the name of their [EventKind].
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event")]
+pub enum Event {
+    /// A device has been seen on a station, after it has been absent for a while.
+    #[serde(rename = "joined")]
+    DeviceJoined {
+        device: Device,
+        station: Station,
+        time: DateTime<Utc>,
+        /// Why the device is considered to be present.
+        reason: String,
+    },
+    /// A device hasn't been seen on a station for longer than the absence timeout.
+    #[serde(rename = "left")]
+    DeviceLeft {
+        device: Device,
+        station: Station,
+        time: DateTime<Utc>,
+        last_seen: DateTime<Utc>,
+        /// Why the device is considered to be gone.
+        reason: String,
+    },
+    /// The presence of a device changed too often in a short time.
+    /// Until the device settles down, it won't join or leave.
+    #[serde(rename = "unstable")]
+    DeviceUnstable {
+        device: Device,
+        station: Station,
+        time: DateTime<Utc>,
+        reason: String,
+    },
+    /// An unstable device settled down.
+    #[serde(rename = "stable")]
+    DeviceStable {
+        device: Device,
+        station: Station,
+        time: DateTime<Utc>,
+        reason: String,
+    },
+    /// A device switched from one station to another, e.g. between the nodes of a mesh network.
+    #[serde(rename = "roamed")]
+    DeviceRoamed {
+        device: Device,
+        from: Station,
+        to: Station,
+        time: DateTime<Utc>,
+    },
+    /// The traffic of a device on a station during a whole minute.
+    #[serde(rename = "traffic")]
+    Traffic {
+        device: Device,
+        station: Station,
+        /// The start of the minute.
+        time: DateTime<Utc>,
+        bytes_per_minute: i32,
+        last_seen: DateTime<Utc>,
+    },
+    /// The traffic of a device exceeded an activity threshold for long enough.
+    #[serde(rename = "active")]
+    DeviceActive {
+        device: Device,
+        station: Station,
+        time: DateTime<Utc>,
+        /// The name of the activity threshold.
+        activity: String,
+        /// The traffic during the last minute.
+        bytes_per_minute: i32,
+    },
+    /// An active device stayed below the idle threshold of an activity for long enough.
+    #[serde(rename = "idle")]
+    DeviceIdle {
+        device: Device,
+        station: Station,
+        time: DateTime<Utc>,
+        /// The name of the activity threshold.
+        activity: String,
+    },
+    /// The smoothed signal strength of a device entered the bounds of a proximity zone.
+    #[serde(rename = "zone_entered")]
+    ZoneEntered {
+        device: Device,
+        station: Station,
+        time: DateTime<Utc>,
+        zone: String,
+        /// The smoothed signal strength in dBm.
+        signal: i32,
+    },
+    /// A device left a proximity zone.
+    #[serde(rename = "zone_left")]
+    ZoneLeft {
+        device: Device,
+        station: Station,
+        time: DateTime<Utc>,
+        zone: String,
+        /// The smoothed signal strength in dBm.
+        /// This is `None`, if the zone has been left because the device left the station.
+        signal: Option<i32>,
+    },
+    /// The first member of a group arrived.
+    #[serde(rename = "group_arrived")]
+    GroupArrived {
+        group: Group,
+        /// The member that arrived.
+        device: Device,
+        station: Station,
+        time: DateTime<Utc>,
+    },
+    /// The last member of a group left.
+    #[serde(rename = "group_left")]
+    GroupLeft {
+        group: Group,
+        /// The member that left.
+        device: Device,
+        station: Station,
+        time: DateTime<Utc>,
+    },
+    /// All members of a group are present.
+    #[serde(rename = "group_complete")]
+    GroupComplete {
+        group: Group,
+        /// The member that arrived last.
+        device: Device,
+        station: Station,
+        time: DateTime<Utc>,
+    },
+    /// A device we've never seen before talked to one of our watched stations.
+    /// Depending on the configured policy, the device is watched or quarantined.
+    #[serde(rename = "new_device")]
+    NewDevice {
+        device: Device,
+        station: Station,
+        time: DateTime<Utc>,
+        /// The signal strength of the first frame in dBm.
+        rssi: Option<i32>,
+    },
+    /// An access point impersonates one of our watched stations.
+    /// Either it uses the ssid of the station with another bssid, or the bssid of the station
+    /// with different security.
+    #[serde(rename = "rogue_access_point")]
+    RogueAccessPoint {
+        /// The watched station, that's being impersonated.
+        station: Station,
+        /// The bssid of the rogue access point.
+        bssid: String,
+        ssid: Option<String>,
+        channel: i32,
+        /// The signal strength of the beacon in dBm.
+        rssi: Option<i32>,
+        time: DateTime<Utc>,
+        reason: String,
+    },
+    /// A quiet window with a summary ended.
+    #[serde(rename = "summary")]
+    Summary {
+        /// The name of the schedule or `pause`.
+        window: String,
+        start: DateTime<Utc>,
+        /// The end of the window.
+        time: DateTime<Utc>,
+        /// The amount of events of each kind during the window.
+        events: BTreeMap<String, usize>,
+        /// The transferred bytes of each device during the window.
+        traffic: BTreeMap<String, i64>,
+    },
+    /// A station we've never seen before sent a beacon.
+    #[serde(rename = "new_station")]
+    NewStation {
+        station: Station,
+        time: DateTime<Utc>,
+    },
+    /// The collector switched to the next channel during a full sweep.
+    #[serde(rename = "sweep_progress")]
+    SweepProgress {
+        time: DateTime<Utc>,
+        channel: i32,
+        /// The amount of channels that have been visited, including this one.
+        done: usize,
+        total: usize,
+    },
+    /// A full sweep over all supported channels finished.
+    #[serde(rename = "sweep_finished")]
+    SweepFinished {
+        time: DateTime<Utc>,
+        /// The channels of all watched stations, which are monitored until the next sweep.
+        watched_channels: Vec<i32>,
+    },
+}
+
+/// The different kinds of events, without any of their data.
+/// This is used to reference events in the configuration file.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Joined,
+    Left,
+    Unstable,
+    Stable,
+    Roamed,
+    Traffic,
+    Active,
+    Idle,
+    ZoneEntered,
+    ZoneLeft,
+    GroupArrived,
+    GroupLeft,
+    GroupComplete,
+    NewDevice,
+    RogueAccessPoint,
+    Summary,
+    NewStation,
+    SweepProgress,
+    SweepFinished,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Joined => "joined",
+            EventKind::Left => "left",
+            EventKind::Unstable => "unstable",
+            EventKind::Stable => "stable",
+            EventKind::Roamed => "roamed",
+            EventKind::Traffic => "traffic",
+            EventKind::Active => "active",
+            EventKind::Idle => "idle",
+            EventKind::ZoneEntered => "zone_entered",
+            EventKind::ZoneLeft => "zone_left",
+            EventKind::GroupArrived => "group_arrived",
+            EventKind::GroupLeft => "group_left",
+            EventKind::GroupComplete => "group_complete",
+            EventKind::NewDevice => "new_device",
+            EventKind::RogueAccessPoint => "rogue_access_point",
+            EventKind::Summary => "summary",
+            EventKind::NewStation => "new_station",
+            EventKind::SweepProgress => "sweep_progress",
+            EventKind::SweepFinished => "sweep_finished",
+        }
+    }
+
+    /// Sweep events are only interesting for diagnostics.
+    /// With additional interfaces, they happen every few seconds.
+    pub fn is_diagnostic(&self) -> bool {
+        matches!(self, EventKind::SweepProgress | EventKind::SweepFinished)
+    }
+
+    /// Check whether this kind is selected by a list of event kinds from the config.
+    /// An empty list selects all events, except for diagnostic ones, which need to be listed explicitly.
+    pub fn selected_by(&self, kinds: &[EventKind]) -> bool {
+        if kinds.is_empty() {
+            !self.is_diagnostic()
+        } else {
+            kinds.contains(self)
+        }
+    }
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::DeviceJoined { .. } => EventKind::Joined,
+            Event::DeviceLeft { .. } => EventKind::Left,
+            Event::DeviceUnstable { .. } => EventKind::Unstable,
+            Event::DeviceStable { .. } => EventKind::Stable,
+            Event::DeviceRoamed { .. } => EventKind::Roamed,
+            Event::Traffic { .. } => EventKind::Traffic,
+            Event::DeviceActive { .. } => EventKind::Active,
+            Event::DeviceIdle { .. } => EventKind::Idle,
+            Event::ZoneEntered { .. } => EventKind::ZoneEntered,
+            Event::ZoneLeft { .. } => EventKind::ZoneLeft,
+            Event::GroupArrived { .. } => EventKind::GroupArrived,
+            Event::GroupLeft { .. } => EventKind::GroupLeft,
+            Event::GroupComplete { .. } => EventKind::GroupComplete,
+            Event::NewDevice { .. } => EventKind::NewDevice,
+            Event::RogueAccessPoint { .. } => EventKind::RogueAccessPoint,
+            Event::Summary { .. } => EventKind::Summary,
+            Event::NewStation { .. } => EventKind::NewStation,
+            Event::SweepProgress { .. } => EventKind::SweepProgress,
+            Event::SweepFinished { .. } => EventKind::SweepFinished,
+        }
+    }
+
+    /// The device this event is about, if there is one.
+    pub fn device(&self) -> Option<&Device> {
+        match self {
+            Event::DeviceJoined { device, .. }
+            | Event::DeviceLeft { device, .. }
+            | Event::DeviceUnstable { device, .. }
+            | Event::DeviceStable { device, .. }
+            | Event::DeviceRoamed { device, .. }
+            | Event::Traffic { device, .. }
+            | Event::DeviceActive { device, .. }
+            | Event::DeviceIdle { device, .. }
+            | Event::ZoneEntered { device, .. }
+            | Event::ZoneLeft { device, .. }
+            | Event::GroupArrived { device, .. }
+            | Event::GroupLeft { device, .. }
+            | Event::GroupComplete { device, .. }
+            | Event::NewDevice { device, .. } => Some(device),
+            Event::RogueAccessPoint { .. }
+            | Event::Summary { .. }
+            | Event::NewStation { .. }
+            | Event::SweepProgress { .. }
+            | Event::SweepFinished { .. } => None,
+        }
+    }
+
+    /// The group this event is about, if there is one.
+    pub fn group(&self) -> Option<&Group> {
+        match self {
+            Event::GroupArrived { group, .. }
+            | Event::GroupLeft { group, .. }
+            | Event::GroupComplete { group, .. } => Some(group),
+            _ => None,
+        }
+    }
+
+    /// The station this event is about, if there is one.
+    /// For roaming devices, this is the station they roamed to.
+    pub fn station(&self) -> Option<&Station> {
+        match self {
+            Event::DeviceRoamed { to, .. } => Some(to),
+            Event::DeviceJoined { station, .. }
+            | Event::DeviceLeft { station, .. }
+            | Event::DeviceUnstable { station, .. }
+            | Event::DeviceStable { station, .. }
+            | Event::Traffic { station, .. }
+            | Event::DeviceActive { station, .. }
+            | Event::DeviceIdle { station, .. }
+            | Event::ZoneEntered { station, .. }
+            | Event::ZoneLeft { station, .. }
+            | Event::GroupArrived { station, .. }
+            | Event::GroupLeft { station, .. }
+            | Event::GroupComplete { station, .. }
+            | Event::NewDevice { station, .. }
+            | Event::RogueAccessPoint { station, .. }
+            | Event::NewStation { station, .. } => Some(station),
+            Event::Summary { .. } | Event::SweepProgress { .. } | Event::SweepFinished { .. } => {
+                None
+            }
+        }
+    }
+
+    /// The time at which this event happened.
+    pub fn time(&self) -> DateTime<Utc> {
+        match self {
+            Event::DeviceJoined { time, .. }
+            | Event::DeviceLeft { time, .. }
+            | Event::DeviceUnstable { time, .. }
+            | Event::DeviceStable { time, .. }
+            | Event::DeviceRoamed { time, .. }
+            | Event::Traffic { time, .. }
+            | Event::DeviceActive { time, .. }
+            | Event::DeviceIdle { time, .. }
+            | Event::ZoneEntered { time, .. }
+            | Event::ZoneLeft { time, .. }
+            | Event::GroupArrived { time, .. }
+            | Event::GroupLeft { time, .. }
+            | Event::GroupComplete { time, .. }
+            | Event::NewDevice { time, .. }
+            | Event::RogueAccessPoint { time, .. }
+            | Event::Summary { time, .. }
+            | Event::NewStation { time, .. }
+            | Event::SweepProgress { time, .. }
+            | Event::SweepFinished { time, .. } => *time,
+        }
+    }
+
+    /// A short, human readable description of the event.
+    pub fn describe(&self) -> String {
+        match self {
+            Event::DeviceJoined {
+                device, station, ..
+            } => format!(
+                "Device {} joined station {}",
+                device_name(device),
+                station_name(station)
+            ),
+            Event::DeviceLeft {
+                device, station, ..
+            } => format!(
+                "Device {} left station {}",
+                device_name(device),
+                station_name(station)
+            ),
+            Event::DeviceUnstable {
+                device, station, ..
+            } => format!(
+                "Device {} is unstable on station {}",
+                device_name(device),
+                station_name(station)
+            ),
+            Event::DeviceStable {
+                device, station, ..
+            } => format!(
+                "Device {} is stable again on station {}",
+                device_name(device),
+                station_name(station)
+            ),
+            Event::DeviceRoamed {
+                device, from, to, ..
+            } => format!(
+                "Device {} roamed from station {} to {}",
+                device_name(device),
+                station_name(from),
+                station_name(to)
+            ),
+            Event::Traffic {
+                device,
+                station,
+                bytes_per_minute,
+                ..
+            } => format!(
+                "Device {} transferred {} bytes on station {}",
+                device_name(device),
+                bytes_per_minute,
+                station_name(station)
+            ),
+            Event::DeviceActive {
+                device, activity, ..
+            } => format!("Device {} started {}", device_name(device), activity),
+            Event::DeviceIdle {
+                device, activity, ..
+            } => format!("Device {} stopped {}", device_name(device), activity),
+            Event::ZoneEntered { device, zone, .. } => {
+                format!("Device {} entered zone {}", device_name(device), zone)
+            }
+            Event::ZoneLeft { device, zone, .. } => {
+                format!("Device {} left zone {}", device_name(device), zone)
+            }
+            Event::GroupArrived { group, device, .. } => format!(
+                "Device {} arrived as first member of group {}",
+                device_name(device),
+                group.name
+            ),
+            Event::GroupLeft { group, device, .. } => format!(
+                "Device {} left as last member of group {}",
+                device_name(device),
+                group.name
+            ),
+            Event::GroupComplete { group, .. } => {
+                format!("All members of group {} are present", group.name)
+            }
+            Event::NewDevice {
+                device,
+                station,
+                rssi,
+                ..
+            } => {
+                let mut description = format!(
+                    "Found new device {} on station {}",
+                    device_name(device),
+                    station_name(station)
+                );
+                if let Some(vendor) = &device.vendor {
+                    description.push_str(&format!(", vendor {}", vendor));
+                }
+                if let Some(rssi) = rssi {
+                    description.push_str(&format!(", signal {}dBm", rssi));
+                }
+                if device.quarantined {
+                    description.push_str(" (quarantined)");
+                }
+                description
+            }
+            Event::RogueAccessPoint {
+                station,
+                bssid,
+                reason,
+                ..
+            } => format!(
+                "Access point {} impersonates station {}: {}",
+                bssid,
+                station_name(station),
+                reason
+            ),
+            Event::Summary { window, events, .. } => {
+                let events: Vec<String> = events
+                    .iter()
+                    .map(|(kind, count)| format!("{} {}", count, kind))
+                    .collect();
+                if events.is_empty() {
+                    format!("Nothing happened during {}", window)
+                } else {
+                    format!("During {}: {}", window, events.join(", "))
+                }
+            }
+            Event::NewStation { station, .. } => {
+                format!("Found new station {}", station_name(station))
+            }
+            Event::SweepProgress {
+                channel,
+                done,
+                total,
+                ..
+            } => format!("Sweeping channel {} ({}/{})", channel, done, total),
+            Event::SweepFinished {
+                watched_channels, ..
+            } => format!(
+                "Full sweep finished, watched channels are {:?}",
+                watched_channels
+            ),
+        }
+    }
+}
+
+/// Get the nickname of a device or fall back to its mac address.
+pub fn device_name(device: &Device) -> String {
+    device
+        .nickname
+        .clone()
+        .unwrap_or_else(|| device.mac_address.to_string())
+}
+
+/// Get the nickname or ssid of a station or fall back to its mac address.
+pub fn station_name(station: &Station) -> String {
+    station
+        .nickname
+        .clone()
+        .or_else(|| station.ssid.clone())
+        .unwrap_or_else(|| station.mac_address.to_string())
+}
+
+/// The central place for distributing events to all interested parts of the daemon.
+///
+/// Every subscriber gets its own receiver and thereby its own copy of each event.
+/// While the bus is muted, only subscribers of [EventBus::subscribe_unmuted] get events.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+    unmuted_sender: broadcast::Sender<Event>,
+    muted: bool,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        let (unmuted_sender, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        EventBus {
+            sender,
+            unmuted_sender,
+            muted: false,
+        }
+    }
+}
+
+impl EventBus {
+    /// Get a new receiver, which receives all events that're published from now on.
+    /// This is used for everything that produces output, e.g. hooks or notifications.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Get a new receiver, which also receives events while the bus is muted.
+    /// This is used for everything that only records events, e.g. the event log.
+    pub fn subscribe_unmuted(&self) -> broadcast::Receiver<Event> {
+        self.unmuted_sender.subscribe()
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Send an event to all current subscribers.
+    pub fn publish(&self, event: Event) {
+        // Traffic is reported every minute for each active device and sweeps report every
+        // channel, which would be too noisy.
+        if matches!(event.kind(), EventKind::Traffic | EventKind::SweepProgress) {
+            debug!("{}", event.describe());
+        } else {
+            info!("{}", event.describe());
+        }
+
+        // This only errors if there are no subscribers, in which case nobody cares about the event.
+        if !self.muted {
+            let _ = self.sender.send(event.clone());
+        }
+        let _ = self.unmuted_sender.send(event);
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use log::{info, warn};
+
+use crate::config::Group as GroupConfig;
+use crate::db::models::*;
+use crate::db::Connection;
+use crate::events::Event;
+use crate::presence::PresenceTracker;
+
+/// A group with its members and the members that're currently present.
+struct TrackedGroup {
+    group: Group,
+    members: HashSet<i32>,
+    /// The nicknames of members, which didn't belong to any device yet.
+    /// They're resolved, as soon as a device with that nickname shows up.
+    unresolved: HashSet<String>,
+    present: HashSet<i32>,
+}
+
+/// Tracks the presence of groups, based on the presence of their members.
+#[derive(Default)]
+pub struct GroupTracker {
+    groups: Vec<TrackedGroup>,
+}
+
+impl GroupTracker {
+    /// Write all groups from the config into the database and track them afterwards.
+    /// Groups, that have been removed from the config, stay in the database for the event log,
+    /// but aren't tracked anymore.
+    pub async fn init(
+        &mut self,
+        connection: &mut Connection,
+        configs: &[GroupConfig],
+        devices: &HashMap<String, Device>,
+    ) -> Result<()> {
+        self.groups = Vec::new();
+        for config in configs {
+            let mut members = HashSet::new();
+            let mut unresolved = HashSet::new();
+            for nickname in config.devices.iter() {
+                match devices
+                    .values()
+                    .find(|device| device.nickname.as_ref() == Some(nickname))
+                {
+                    Some(device) => {
+                        members.insert(device.id);
+                    }
+                    None => {
+                        warn!(
+                            "Unknown device {} in group {}. It's added, once it shows up with this nickname.",
+                            nickname, config.name
+                        );
+                        unresolved.insert(nickname.clone());
+                    }
+                }
+            }
+
+            let group = Group::get_or_create(connection, &config.name).await?;
+            let member_ids: Vec<i32> = members.iter().copied().collect();
+            group.set_members(connection, &member_ids).await?;
+
+            self.groups.push(TrackedGroup {
+                group,
+                members,
+                unresolved,
+                present: HashSet::new(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Check whether any member of the group is present.
+    pub fn is_arrived(&self, group_id: i32) -> bool {
+        self.groups
+            .iter()
+            .any(|tracked| tracked.group.id == group_id && !tracked.present.is_empty())
+    }
+
+    /// Check whether all members of the group are present.
+    pub fn is_complete(&self, group_id: i32) -> bool {
+        self.groups
+            .iter()
+            .any(|tracked| tracked.group.id == group_id && tracked.is_complete())
+    }
+
+    /// Update all groups of the device, whose presence just changed.
+    /// Returns the resulting group events.
+    pub fn handle_event(&mut self, event: &Event, presence: &PresenceTracker) -> Vec<Event> {
+        let (device, station, time) = match event {
+            Event::DeviceJoined {
+                device,
+                station,
+                time,
+                ..
+            }
+            | Event::DeviceLeft {
+                device,
+                station,
+                time,
+                ..
+            } => (device, station, *time),
+            _ => return Vec::new(),
+        };
+
+        // A device counts as present, as long as it's online on any station.
+        let is_present = presence.is_device_online(device.id);
+
+        let mut events = Vec::new();
+        for tracked in self.groups.iter_mut() {
+            tracked.resolve(device);
+            if !tracked.members.contains(&device.id) {
+                continue;
+            }
+
+            let was_empty = tracked.present.is_empty();
+            let was_complete = tracked.is_complete();
+            if is_present {
+                tracked.present.insert(device.id);
+            } else {
+                tracked.present.remove(&device.id);
+            }
+
+            let group = tracked.group.clone();
+            let device = device.clone();
+            let station = station.clone();
+            if was_empty && !tracked.present.is_empty() {
+                events.push(Event::GroupArrived {
+                    group: group.clone(),
+                    device: device.clone(),
+                    station: station.clone(),
+                    time,
+                });
+            }
+            if !was_complete && tracked.is_complete() {
+                events.push(Event::GroupComplete {
+                    group,
+                    device,
+                    station,
+                    time,
+                });
+            } else if !was_empty && tracked.present.is_empty() {
+                events.push(Event::GroupLeft {
+                    group,
+                    device,
+                    station,
+                    time,
+                });
+            }
+        }
+
+        events
+    }
+}
+
+impl TrackedGroup {
+    /// Add the device to the members, if it has the nickname of an unresolved member.
+    fn resolve(&mut self, device: &Device) {
+        let nickname = match &device.nickname {
+            Some(nickname) => nickname,
+            None => return,
+        };
+
+        if self.unresolved.remove(nickname) {
+            info!(
+                "Device {} is now a member of group {}",
+                nickname, self.group.name
+            );
+            self.members.insert(device.id);
+        }
+    }
+
+    /// A group is only complete, once all members are known and present.
+    fn is_complete(&self) -> bool {
+        self.unresolved.is_empty() && self.present == self.members
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    use super::*;
+    use crate::config;
+    use crate::events::EventKind;
+    use crate::presence::Activity;
+
+    fn time(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 17, hour, 0, 0).unwrap()
+    }
+
+    fn device(id: i32) -> Device {
+        Device {
+            id,
+            mac_address: format!("02:00:00:00:00:{:02x}", id).parse().unwrap(),
+            nickname: Some(format!("device-{}", id)),
+            description: None,
+            watch: true,
+            vendor: None,
+            quarantined: false,
+        }
+    }
+
+    fn station() -> Station {
+        Station {
+            id: 1,
+            mac_address: "02:00:00:00:01:01".parse().unwrap(),
+            ssid: Some("home".into()),
+            channel: 6,
+            power_level: None,
+            nickname: None,
+            description: None,
+            watch: true,
+            protected: Some(true),
+        }
+    }
+
+    fn tracker(members: &[i32], unresolved: &[&str]) -> GroupTracker {
+        GroupTracker {
+            groups: vec![TrackedGroup {
+                group: Group {
+                    id: 1,
+                    name: "family".into(),
+                },
+                members: members.iter().copied().collect(),
+                unresolved: unresolved.iter().map(|name| name.to_string()).collect(),
+                present: HashSet::new(),
+            }],
+        }
+    }
+
+    fn presence() -> PresenceTracker {
+        let config = config::Presence {
+            arrival_frames: 1,
+            flap_threshold: 0,
+            ..Default::default()
+        };
+        PresenceTracker::new(&config).unwrap()
+    }
+
+    fn join(groups: &mut GroupTracker, presence: &mut PresenceTracker, id: i32) -> Vec<EventKind> {
+        let activity = Activity {
+            device: device(id),
+            station: station(),
+            time: time(12),
+            bytes: 100,
+            rssi: None,
+        };
+        let event = presence.handle_activity(activity).unwrap();
+        let events = groups.handle_event(&event, presence);
+        events.iter().map(Event::kind).collect()
+    }
+
+    fn leave(groups: &mut GroupTracker, presence: &mut PresenceTracker, id: i32) -> Vec<EventKind> {
+        presence.presences.get_mut(&(id, 1)).unwrap().online = false;
+        let event = Event::DeviceLeft {
+            device: device(id),
+            station: station(),
+            time: time(13),
+            last_seen: time(12),
+            reason: String::new(),
+        };
+        let events = groups.handle_event(&event, presence);
+        events.iter().map(Event::kind).collect()
+    }
+
+    #[test]
+    fn arrives_completes_and_leaves() {
+        let mut groups = tracker(&[1, 2], &[]);
+        let mut presence = presence();
+
+        assert_eq!(
+            join(&mut groups, &mut presence, 1),
+            vec![EventKind::GroupArrived]
+        );
+        assert!(groups.is_arrived(1));
+        assert!(!groups.is_complete(1));
+        assert_eq!(
+            join(&mut groups, &mut presence, 2),
+            vec![EventKind::GroupComplete]
+        );
+        assert!(groups.is_complete(1));
+
+        assert!(leave(&mut groups, &mut presence, 1).is_empty());
+        assert!(!groups.is_complete(1));
+        assert_eq!(
+            leave(&mut groups, &mut presence, 2),
+            vec![EventKind::GroupLeft]
+        );
+        assert!(!groups.is_arrived(1));
+    }
+
+    #[test]
+    fn single_member_arrives_and_completes() {
+        let mut groups = tracker(&[1], &[]);
+        let mut presence = presence();
+
+        assert_eq!(
+            join(&mut groups, &mut presence, 1),
+            vec![EventKind::GroupArrived, EventKind::GroupComplete]
+        );
+        assert_eq!(
+            leave(&mut groups, &mut presence, 1),
+            vec![EventKind::GroupLeft]
+        );
+    }
+
+    #[test]
+    fn ignores_other_devices() {
+        let mut groups = tracker(&[1], &[]);
+        let mut presence = presence();
+
+        assert!(join(&mut groups, &mut presence, 3).is_empty());
+        assert!(!groups.is_arrived(1));
+    }
+
+    #[test]
+    fn resolves_unknown_members() {
+        let mut groups = tracker(&[1], &["device-2"]);
+        let mut presence = presence();
+
+        // The group can't be complete, as long as a member is unknown.
+        assert_eq!(
+            join(&mut groups, &mut presence, 1),
+            vec![EventKind::GroupArrived]
+        );
+        assert!(!groups.is_complete(1));
+
+        assert_eq!(
+            join(&mut groups, &mut presence, 2),
+            vec![EventKind::GroupComplete]
+        );
+        assert!(groups.groups[0].members.contains(&2));
+    }
+}
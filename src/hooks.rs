@@ -0,0 +1,41 @@
+use log::warn;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::task::JoinHandle;
+
+use crate::config::Hooks;
+use crate::events::Event;
+use crate::supervisor::Supervisor;
+
+/// Spawn the task that executes all configured hooks for incoming events.
+pub fn spawn_hook_runner(
+    hooks: Hooks,
+    supervisor: Supervisor,
+    mut receiver: Receiver<Event>,
+) -> Option<JoinHandle<()>> {
+    if hooks.commands.is_empty() {
+        return None;
+    }
+
+    let task = tokio::spawn(async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(count)) => {
+                    warn!("Hook runner couldn't keep up and missed {} events", count);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            for hook in hooks.commands.iter() {
+                if hook.filter.matches(&event) {
+                    let name = hook.name.as_ref().unwrap_or(&hook.command);
+                    supervisor.run(name, &hook.command, hook.timeout, &event);
+                }
+            }
+        }
+    });
+
+    Some(task)
+}
@@ -0,0 +1,55 @@
+//! Wifitify tracks the activity of wifi devices and publishes events about them.
+//!
+//! Besides running it as a daemon, the collector can be embedded into other applications:
+//!
+//! ```no_run
+//! use wifitify::config::Config;
+//! use wifitify::events::Event;
+//! use wifitify::{Daemon, EventSink, Source};
+//!
+//! struct Printer;
+//!
+//! impl EventSink for Printer {
+//!     fn handle_event(&mut self, event: &Event) {
+//!         println!("{}", event.describe());
+//!     }
+//! }
+//!
+//! # async fn embed() -> anyhow::Result<()> {
+//! let mut daemon = Daemon::new(Config::new()?).await?;
+//! daemon.add_sink(Printer);
+//! daemon.run(Source::Devices(vec!["wlan0".into()])).await
+//! # }
+//! ```
+
+mod activity;
+pub mod config;
+mod daemon;
+mod data;
+pub mod db;
+mod device;
+mod event_log;
+pub mod events;
+mod groups;
+mod hooks;
+mod listener;
+mod mqtt;
+mod notifications;
+mod presence;
+mod quiet;
+mod roaming;
+mod rules;
+mod scripts;
+mod sink;
+mod socket;
+mod state;
+mod supervisor;
+mod sweeper;
+mod traffic;
+mod vendor;
+mod webhooks;
+mod wifi;
+mod zones;
+
+pub use daemon::{Daemon, Source};
+pub use sink::EventSink;
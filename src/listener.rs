@@ -1,32 +1,109 @@
+use std::path::Path;
+
 use anyhow::Result;
-use crossbeam_channel::Sender;
+use chrono::{DateTime, Utc};
+use crossbeam_channel::{Receiver, Sender};
 use libwifi::Frame;
+use log::{debug, error, info};
 use radiotap::Radiotap;
 
 use crate::wifi::capture::*;
+use crate::wifi::recorder::SharedRecorder;
 
 /// Initialize the thread that's listening for radio packages.
 /// All received packets are then send to the main thread via a mpsc channel.
-pub fn init_packet_listener_thread(device: &str, sender: Sender<(Frame, Radiotap)>) -> Result<()> {
+/// If a recorder is given, all raw packets are recorded before they're parsed.
+///
+/// New BPF filter programs can be sent via `filters` at any time. They're applied before the
+/// next packet is read, which is why the filters should always let beacons through.
+pub fn init_packet_listener_thread(
+    device: &str,
+    recorder: Option<SharedRecorder>,
+    filters: Receiver<String>,
+    sender: Sender<(Frame, Radiotap, DateTime<Utc>)>,
+) -> Result<()> {
     // The data capture and parsing logic is running in its own thread.
     // This allows us to have all receiving logic in a non-blocking fashion.
     // The actual handling of the received frames can then be done in an async fashion, since
     // there'll be a lot of I/O wait when interacting with the database.
     let mut capture = get_capture(device)?;
 
-    std::thread::spawn(move || {
-        while let Ok(packet) = capture.next_packet() {
-            let data = handle_packet(packet);
-            if let Ok(data) = data {
+    std::thread::spawn(move || loop {
+        // Only the latest filter is of interest, in case several have been sent in the meantime.
+        if let Some(filter) = filters.try_iter().last() {
+            match capture.filter(&filter, true) {
+                Ok(()) => debug!("Applied capture filter: {}", filter),
+                Err(err) => error!("Failed to apply capture filter {}: {:?}", filter, err),
+            }
+        }
+
+        let packet = match capture.next_packet() {
+            Ok(packet) => packet,
+            Err(_) => return,
+        };
+
+        if let Some(recorder) = &recorder {
+            if let Ok(mut recorder) = recorder.lock() {
+                recorder.record(&packet);
+            }
+        }
+
+        match handle_packet(packet) {
+            Ok(data) => {
                 // Send extracted data to the receiver.
                 // This only errors if the receiver went away, in which case we just bail.
                 if sender.send(data).is_err() {
                     return;
                 };
-            } else {
-                //println!("Got error: {:?}", data);
+            }
+            Err(err) => debug!("Couldn't parse packet: {:?}", err),
+        }
+    });
+
+    Ok(())
+}
+
+/// Initialize the thread that's reading packets from a recorded capture file.
+/// Once all packets have been read, the sender is dropped, which lets the main thread know.
+///
+/// Packets are read as fast as possible, unless `realtime` is set. In that case, the delays
+/// between packets are the same as while recording.
+pub fn init_file_reader_thread(
+    path: &Path,
+    realtime: bool,
+    sender: Sender<(Frame, Radiotap, DateTime<Utc>)>,
+) -> Result<()> {
+    let mut capture = get_file_capture(path)?;
+
+    std::thread::spawn(move || {
+        let mut previous: Option<DateTime<Utc>> = None;
+        loop {
+            let packet = match capture.next_packet() {
+                Ok(packet) => packet,
+                Err(pcap::Error::NoMorePackets) => break,
+                Err(err) => {
+                    error!("Failed to read capture file: {:?}", err);
+                    break;
+                }
+            };
+
+            if realtime {
+                let time = packet_time(&packet);
+                if let Some(delay) = previous.and_then(|previous| (time - previous).to_std().ok()) {
+                    std::thread::sleep(delay);
+                }
+                previous = Some(time);
+            }
+
+            if let Ok(data) = handle_packet(packet) {
+                // This only errors if the receiver went away, in which case we just bail.
+                if sender.send(data).is_err() {
+                    return;
+                };
             }
         }
+
+        info!("Finished reading capture file");
     });
 
     Ok(())
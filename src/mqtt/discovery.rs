@@ -0,0 +1,203 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{error, info};
+use rumqttc::{AsyncClient, QoS};
+use serde_json::{json, Value};
+
+use super::{device_topic, station_topic};
+use crate::config::Mqtt;
+use crate::db::models::*;
+use crate::db::DbPool;
+use crate::events::{device_name, station_name};
+
+/// Spawn the task that announces all watched devices and stations to Home Assistant.
+///
+/// Only devices, that have been seen on one of the watched stations, are announced.
+/// The database is checked periodically, so devices and stations are announced as soon as
+/// they're watched and removed from Home Assistant as soon as they're no longer watched.
+pub fn spawn_discovery(config: Mqtt, client: AsyncClient, pool: DbPool) {
+    tokio::spawn(async move {
+        let mut discovery = Discovery {
+            config,
+            client,
+            announced: HashMap::new(),
+            initialized: false,
+        };
+
+        loop {
+            if let Err(err) = discovery.sync(&pool).await {
+                error!("Failed to sync Home Assistant discovery: {:?}", err);
+            }
+
+            let interval = discovery.config.discovery_interval;
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+        }
+    });
+}
+
+struct Discovery {
+    config: Mqtt,
+    client: AsyncClient,
+    /// The discovery config topics, that have been announced, with their current payload.
+    announced: HashMap<String, Value>,
+    /// Whether the first sync has been done.
+    /// On the first sync, entities of unwatched devices on watched stations and of unwatched
+    /// stations with a nickname are removed as well, since they might have been unwatched while
+    /// the daemon wasn't running. Everything else (e.g. the neighbours) has never been announced.
+    initialized: bool,
+}
+
+impl Discovery {
+    async fn sync(&mut self, pool: &DbPool) -> Result<()> {
+        let mut connection = pool.acquire().await?;
+        let devices = Device::known_devices(&mut connection).await?;
+        let stations = Station::known_stations(&mut connection).await?;
+        let station_device_map = DeviceStation::get_station_device_map(&mut connection).await?;
+        drop(connection);
+
+        // All devices, that have been seen on any of the watched stations.
+        let home_devices: HashSet<i32> = stations
+            .values()
+            .filter(|station| station.watch)
+            .filter_map(|station| station_device_map.get(&station.id))
+            .flatten()
+            .copied()
+            .collect();
+
+        for device in devices.values() {
+            let home = home_devices.contains(&device.id);
+            let entities = self.device_entities(device);
+            self.update(entities, home && device.watch, home).await;
+        }
+        for station in stations.values() {
+            let entities = self.station_entities(station);
+            let named = station.nickname.is_some();
+            self.update(entities, station.watch, named).await;
+        }
+
+        self.initialized = true;
+
+        Ok(())
+    }
+
+    /// Announce or remove entities, depending on whether their device/station is watched.
+    /// On the first sync, entities are only removed, if they might have been announced before.
+    async fn update(
+        &mut self,
+        entities: Vec<(String, Value)>,
+        watch: bool,
+        announced_before: bool,
+    ) {
+        for (topic, payload) in entities {
+            if watch {
+                // Only publish, if the entity is new or its config changed (e.g. a new nickname).
+                if self.announced.get(&topic) == Some(&payload) {
+                    continue;
+                }
+                info!("Announcing Home Assistant entity {}", topic);
+                self.publish(&topic, payload.to_string()).await;
+                self.announced.insert(topic, payload);
+            } else if self.announced.remove(&topic).is_some()
+                || (!self.initialized && announced_before)
+            {
+                // An empty retained message removes the entity from Home Assistant.
+                self.publish(&topic, String::new()).await;
+            }
+        }
+    }
+
+    async fn publish(&self, topic: &str, payload: String) {
+        if let Err(err) = self
+            .client
+            .publish(topic, QoS::AtLeastOnce, true, payload)
+            .await
+        {
+            error!("Failed to publish MQTT discovery message: {:?}", err);
+        }
+    }
+
+    /// The `device_tracker`, last-seen and traffic entities of a device.
+    fn device_entities(&self, device: &Device) -> Vec<(String, Value)> {
+        let name = device_name(device);
+        let mac = device.mac_address.to_string();
+        let id = format!("wifitify_device_{}", mac.replace(':', ""));
+        let ha_device = json!({
+            "identifiers": [id],
+            "connections": [["mac", mac]],
+            "name": name,
+            "manufacturer": "wifitify",
+        });
+
+        vec![
+            (
+                self.config_topic("device_tracker", &id),
+                json!({
+                    "name": null,
+                    "unique_id": id,
+                    "state_topic": device_topic(&self.config.presence_topic, &name),
+                    "payload_home": "home",
+                    "payload_not_home": "not_home",
+                    "source_type": "router",
+                    "availability_topic": self.config.status_topic,
+                    "device": ha_device,
+                }),
+            ),
+            (
+                self.config_topic("sensor", &format!("{}_last_seen", id)),
+                json!({
+                    "name": "Last seen",
+                    "unique_id": format!("{}_last_seen", id),
+                    "state_topic": device_topic(&self.config.last_seen_topic, &name),
+                    "device_class": "timestamp",
+                    "availability_topic": self.config.status_topic,
+                    "device": ha_device,
+                }),
+            ),
+            (
+                self.config_topic("sensor", &format!("{}_traffic", id)),
+                json!({
+                    "name": "Traffic",
+                    "unique_id": format!("{}_traffic", id),
+                    "state_topic": device_topic(&self.config.traffic_topic, &name),
+                    "unit_of_measurement": "B/min",
+                    "state_class": "measurement",
+                    "availability_topic": self.config.status_topic,
+                    "device": ha_device,
+                }),
+            ),
+        ]
+    }
+
+    /// The sensor entity with the amount of present devices on a station.
+    fn station_entities(&self, station: &Station) -> Vec<(String, Value)> {
+        let name = station_name(station);
+        let mac = station.mac_address.to_string();
+        let id = format!("wifitify_station_{}", mac.replace(':', ""));
+
+        vec![(
+            self.config_topic("sensor", &format!("{}_devices", id)),
+            json!({
+                "name": "Present devices",
+                "unique_id": format!("{}_devices", id),
+                "state_topic": station_topic(&self.config.station_devices_topic, &name),
+                "state_class": "measurement",
+                "availability_topic": self.config.status_topic,
+                "device": {
+                    "identifiers": [id],
+                    "connections": [["mac", mac]],
+                    "name": name,
+                    "manufacturer": "wifitify",
+                },
+            }),
+        )]
+    }
+
+    fn config_topic(&self, component: &str, object_id: &str) -> String {
+        format!(
+            "{}/{}/{}/config",
+            self.config.discovery_prefix, component, object_id
+        )
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use rumqttc::{AsyncClient, Event as MqttEvent, LastWill, MqttOptions, Packet, QoS};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::task::JoinHandle;
+
+use crate::config::Mqtt;
+use crate::db::models::Station;
+use crate::events::{device_name, station_name, Event};
+
+pub mod discovery;
+
+/// Connect to the MQTT broker.
+///
+/// The connection is driven by a background task, which also reconnects on errors.
+/// The daemon's status is set to `online` on every (re)connect and the broker sets it to
+/// `offline` via the last will, if we disconnect.
+pub fn connect(config: &Mqtt) -> AsyncClient {
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    options.set_last_will(LastWill::new(
+        &config.status_topic,
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        options.set_credentials(username, password);
+    }
+
+    let (client, mut eventloop) = AsyncClient::new(options, 100);
+
+    let status_client = client.clone();
+    let status_topic = config.status_topic.clone();
+    tokio::spawn(async move {
+        loop {
+            match eventloop.poll().await {
+                Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to MQTT broker");
+                    if let Err(err) =
+                        status_client.try_publish(&status_topic, QoS::AtLeastOnce, true, "online")
+                    {
+                        error!("Failed to publish MQTT status: {:?}", err);
+                    }
+                }
+                Ok(_) => (),
+                Err(err) => {
+                    warn!("MQTT connection error, reconnecting: {:?}", err);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    });
+
+    client
+}
+
+/// Spawn the task that publishes the presence, last-seen time, traffic and activity of devices.
+/// For each station, the amount of present devices is published as well.
+pub fn spawn_mqtt_publisher(
+    config: Mqtt,
+    client: AsyncClient,
+    mut receiver: Receiver<Event>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        // All `(device id, station id)` tuples, which are currently online.
+        // A device is home, as long as it's online on any station.
+        let mut online = HashSet::new();
+
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(count)) => {
+                    warn!(
+                        "MQTT publisher couldn't keep up and missed {} events",
+                        count
+                    );
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            let messages = match &event {
+                Event::DeviceJoined {
+                    device,
+                    station,
+                    time,
+                    ..
+                } => {
+                    online.insert((device.id, station.id));
+                    vec![
+                        (
+                            device_topic(&config.presence_topic, &device_name(device)),
+                            "home".to_string(),
+                        ),
+                        (
+                            device_topic(&config.last_seen_topic, &device_name(device)),
+                            time.to_rfc3339(),
+                        ),
+                        station_devices_message(&config, &online, station),
+                    ]
+                }
+                Event::DeviceLeft {
+                    device, station, ..
+                } => {
+                    online.remove(&(device.id, station.id));
+                    let mut messages = vec![station_devices_message(&config, &online, station)];
+
+                    // The device is still home, as long as it's online on another station.
+                    if !online.iter().any(|(device_id, _)| *device_id == device.id) {
+                        messages.push((
+                            device_topic(&config.presence_topic, &device_name(device)),
+                            "not_home".to_string(),
+                        ));
+                    }
+                    messages
+                }
+                Event::Traffic {
+                    device,
+                    bytes_per_minute,
+                    last_seen,
+                    ..
+                } => vec![
+                    (
+                        device_topic(&config.last_seen_topic, &device_name(device)),
+                        last_seen.to_rfc3339(),
+                    ),
+                    (
+                        device_topic(&config.traffic_topic, &device_name(device)),
+                        bytes_per_minute.to_string(),
+                    ),
+                ],
+                Event::DeviceActive {
+                    device, activity, ..
+                } => vec![(
+                    activity_topic(&config.activity_topic, &device_name(device), activity),
+                    "active".to_string(),
+                )],
+                Event::DeviceIdle {
+                    device, activity, ..
+                } => vec![(
+                    activity_topic(&config.activity_topic, &device_name(device), activity),
+                    "idle".to_string(),
+                )],
+                _ => continue,
+            };
+
+            for (topic, payload) in messages {
+                if let Err(err) = client
+                    .publish(topic, QoS::AtLeastOnce, config.retain, payload)
+                    .await
+                {
+                    error!("Failed to publish MQTT message: {:?}", err);
+                }
+            }
+        }
+    })
+}
+
+/// Build the message containing the amount of devices, that're currently online on a station.
+fn station_devices_message(
+    config: &Mqtt,
+    online: &HashSet<(i32, i32)>,
+    station: &Station,
+) -> (String, String) {
+    let count = online
+        .iter()
+        .filter(|(_, station_id)| *station_id == station.id)
+        .count();
+
+    (
+        station_topic(&config.station_devices_topic, &station_name(station)),
+        count.to_string(),
+    )
+}
+
+/// Insert a device name into a topic template.
+pub fn device_topic(template: &str, name: &str) -> String {
+    template.replace("{device}", &sanitize(name))
+}
+
+/// Insert a station name into a topic template.
+pub fn station_topic(template: &str, name: &str) -> String {
+    template.replace("{station}", &sanitize(name))
+}
+
+/// Insert a device name and the name of an activity into a topic template.
+fn activity_topic(template: &str, name: &str, activity: &str) -> String {
+    device_topic(template, name).replace("{activity}", &sanitize(activity))
+}
+
+/// MQTT wildcards and topic separators in names are replaced, so they don't break the topic.
+fn sanitize(name: &str) -> String {
+    name.replace(['/', '+', '#'], "_")
+}
@@ -0,0 +1,136 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use log::{error, warn};
+use minijinja::{Environment, Error as TemplateError, ErrorKind};
+use reqwest::Client;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::task::JoinHandle;
+
+use crate::config::{Notification, NotificationOutput};
+use crate::events::Event;
+
+/// The timeout of a single http notification.
+/// A hanging target would otherwise hold back all further notifications.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Spawn the task that renders and sends out all notifications.
+///
+/// All templates are compiled right away, so syntax errors are reported on startup.
+pub fn spawn_notifier(
+    notifications: Vec<Notification>,
+    mut receiver: Receiver<Event>,
+) -> Result<Option<JoinHandle<()>>> {
+    if notifications.is_empty() {
+        return Ok(None);
+    }
+
+    let mut env = Environment::new();
+    env.add_filter("local", local_time);
+    for (index, notification) in notifications.iter().enumerate() {
+        env.add_template_owned(index.to_string(), notification.template.clone())
+            .context(format!(
+                "Invalid notification template {}",
+                notification.template
+            ))?;
+    }
+
+    let client = Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .context("Failed to create http client for notifications")?;
+    let task = tokio::spawn(async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(count)) => {
+                    warn!("Notifier couldn't keep up and missed {} events", count);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            for (index, notification) in notifications.iter().enumerate() {
+                if !event.kind().selected_by(&notification.events) {
+                    continue;
+                }
+
+                let rendered = match env
+                    .get_template(&index.to_string())
+                    .and_then(|template| template.render(&event))
+                {
+                    Ok(rendered) => rendered,
+                    Err(err) => {
+                        error!("Failed to render notification: {:?}", err);
+                        continue;
+                    }
+                };
+
+                if let Err(err) = send(&client, notification, rendered).await {
+                    error!("Failed to send notification: {:?}", err);
+                }
+            }
+        }
+    });
+
+    Ok(Some(task))
+}
+
+/// Write a rendered notification to its output.
+async fn send(client: &Client, notification: &Notification, rendered: String) -> Result<()> {
+    match &notification.output {
+        NotificationOutput::Stdout => println!("{}", rendered),
+        NotificationOutput::File(path) => {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await
+                .context(format!("Couldn't open notification file {:?}", path))?;
+            file.write_all(format!("{}\n", rendered).as_bytes()).await?;
+        }
+        NotificationOutput::Http(url) => {
+            let content_type = notification
+                .content_type
+                .clone()
+                .unwrap_or_else(|| "text/plain".into());
+            client
+                .post(url)
+                .header("Content-Type", content_type)
+                .body(rendered)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Template filter, that converts a serialized timestamp to local time.
+/// An optional [strftime](chrono::format::strftime) format string can be passed.
+fn local_time(value: String, format: Option<String>) -> Result<String, TemplateError> {
+    let time = DateTime::parse_from_rfc3339(&value).map_err(|err| {
+        TemplateError::new(
+            ErrorKind::InvalidOperation,
+            format!("Invalid timestamp {}: {}", value, err),
+        )
+    })?;
+
+    // Formatting fails on invalid format strings, which is why `to_string` would panic.
+    let format = format.unwrap_or_else(|| "%Y-%m-%d %H:%M:%S".into());
+    let mut output = String::new();
+    write!(output, "{}", time.with_timezone(&Local).format(&format)).map_err(|_| {
+        TemplateError::new(
+            ErrorKind::InvalidOperation,
+            format!("Invalid time format {}", format),
+        )
+    })?;
+
+    Ok(output)
+}
@@ -0,0 +1,417 @@
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::config;
+use crate::db::models::*;
+use crate::events::Event;
+
+/// A data frame that has been attributed to a watched device on a watched station.
+/// These are sent from the packet handlers to the main thread.
+pub struct Activity {
+    pub device: Device,
+    pub station: Station,
+    /// The time the frame has been captured.
+    pub time: DateTime<Utc>,
+    /// The amount of transferred bytes.
+    pub bytes: i32,
+    /// The signal strength in dBm, if the frame has been sent by the device.
+    pub rssi: Option<i32>,
+}
+
+/// The presence of a single device on a single station.
+pub struct Presence {
+    pub device: Device,
+    pub station: Station,
+    /// The presence, that has been published last.
+    pub online: bool,
+    /// The presence, that's currently estimated from the seen frames.
+    /// This only differs from `online`, while the device is unstable.
+    estimate: bool,
+    /// The time of the last frame we've seen between the device and the station.
+    pub last_seen: DateTime<Utc>,
+    /// The times of the frames that have been seen while the device was absent.
+    /// These are used to confirm the arrival of the device.
+    arrival_frames: VecDeque<DateTime<Utc>>,
+    /// The times of all presence changes during the flap window.
+    transitions: VecDeque<DateTime<Utc>>,
+    /// The presence of the device changed too often.
+    /// Until it settles down, changes aren't published.
+    unstable: bool,
+}
+
+/// Decides whether devices are currently present on stations, based on the activity we see.
+///
+/// Devices in power-save mode may not send any frames for minutes, which is why arrivals and
+/// departures have separate confirmation windows. Devices that still change their presence too
+/// often are marked as unstable and their changes are suppressed.
+pub struct PresenceTracker {
+    /// Devices that haven't been seen for this long are considered to have left.
+    absence_timeout: TimeDelta,
+    /// Absent devices need to send `arrival_frames` during this window to be considered present.
+    arrival_window: TimeDelta,
+    arrival_frames: usize,
+    /// Devices with `flap_threshold` presence changes during this window are unstable.
+    flap_window: TimeDelta,
+    flap_threshold: usize,
+    /// The presence for each `(device id, station id)` tuple we've seen so far.
+    pub presences: HashMap<(i32, i32), Presence>,
+}
+
+impl PresenceTracker {
+    pub fn new(config: &config::Presence) -> Result<Self> {
+        Ok(PresenceTracker {
+            absence_timeout: TimeDelta::try_seconds(config.absence_timeout)
+                .context("Invalid presence 'absence_timeout'")?,
+            arrival_window: TimeDelta::try_seconds(config.arrival_window)
+                .context("Invalid presence 'arrival_window'")?,
+            arrival_frames: config.arrival_frames,
+            flap_window: TimeDelta::try_seconds(config.flap_window)
+                .context("Invalid presence 'flap_window'")?,
+            flap_threshold: config.flap_threshold,
+            presences: HashMap::new(),
+        })
+    }
+
+    /// Update the presence of a device with some new activity.
+    /// If this confirms the arrival of the device, a [Event::DeviceJoined] is returned.
+    pub fn handle_activity(&mut self, activity: Activity) -> Option<Event> {
+        let key = (activity.device.id, activity.station.id);
+
+        let presence = self.presences.entry(key).or_insert_with(|| Presence {
+            device: activity.device.clone(),
+            station: activity.station.clone(),
+            online: false,
+            estimate: false,
+            last_seen: activity.time,
+            arrival_frames: VecDeque::new(),
+            transitions: VecDeque::new(),
+            unstable: false,
+        });
+
+        // Packet handlers run concurrently, which is why activities might arrive out of order.
+        if activity.time > presence.last_seen {
+            presence.last_seen = activity.time;
+        }
+        // Always keep the newest metadata around.
+        presence.device = activity.device;
+        presence.station = activity.station;
+
+        if presence.estimate {
+            return None;
+        }
+
+        // Only count frames that have been seen during the arrival window.
+        presence.arrival_frames.push_back(activity.time);
+        let window_start = presence.last_seen - self.arrival_window;
+        prune(&mut presence.arrival_frames, window_start);
+        if presence.arrival_frames.len() < self.arrival_frames {
+            return None;
+        }
+
+        let reason = format!(
+            "Seen {} frames within {} seconds",
+            presence.arrival_frames.len(),
+            self.arrival_window.num_seconds()
+        );
+        presence.arrival_frames.clear();
+
+        self.change(key, true, activity.time, reason)
+    }
+
+    /// Mark all devices as absent, that haven't been seen for longer than the absence timeout.
+    /// Unstable devices, that settled down, are marked as stable again.
+    pub fn check_timeouts(&mut self, now: DateTime<Utc>) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        let timed_out: Vec<(i32, i32)> = self
+            .presences
+            .iter()
+            .filter(|(_, presence)| {
+                presence.estimate && (now - presence.last_seen) > self.absence_timeout
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        for key in timed_out {
+            let reason = format!(
+                "Not seen for more than {} seconds",
+                self.absence_timeout.num_seconds()
+            );
+            events.extend(self.change(key, false, now, reason));
+        }
+
+        for presence in self.presences.values_mut() {
+            if !presence.unstable {
+                continue;
+            }
+
+            prune(&mut presence.transitions, now - self.flap_window);
+            if !presence.transitions.is_empty() {
+                continue;
+            }
+
+            presence.unstable = false;
+            events.push(Event::DeviceStable {
+                device: presence.device.clone(),
+                station: presence.station.clone(),
+                time: now,
+                reason: format!(
+                    "Presence didn't change for {} seconds",
+                    self.flap_window.num_seconds()
+                ),
+            });
+
+            // Publish the presence the device settled on, if it differs from the last one.
+            if presence.online != presence.estimate {
+                presence.online = presence.estimate;
+                events.push(presence_event(
+                    presence,
+                    now,
+                    "Settled after being unstable".into(),
+                ));
+            }
+        }
+
+        events
+    }
+
+    /// Change the estimated presence of a device and check whether it's flapping.
+    /// Returns the event that should be published for this change, if any.
+    fn change(
+        &mut self,
+        key: (i32, i32),
+        online: bool,
+        time: DateTime<Utc>,
+        reason: String,
+    ) -> Option<Event> {
+        let presence = self.presences.get_mut(&key)?;
+        presence.estimate = online;
+        presence.transitions.push_back(time);
+        prune(&mut presence.transitions, time - self.flap_window);
+
+        // Changes of unstable devices are only published, once they settled down.
+        if presence.unstable {
+            return None;
+        }
+
+        if self.flap_threshold > 0 && presence.transitions.len() >= self.flap_threshold {
+            presence.unstable = true;
+            return Some(Event::DeviceUnstable {
+                device: presence.device.clone(),
+                station: presence.station.clone(),
+                time,
+                reason: format!(
+                    "Presence changed {} times within {} seconds",
+                    presence.transitions.len(),
+                    self.flap_window.num_seconds()
+                ),
+            });
+        }
+
+        presence.online = online;
+        Some(presence_event(presence, time, reason))
+    }
+
+    /// Check whether a device is currently online on a specific station.
+    pub fn is_online(&self, device_id: i32, station_id: i32) -> bool {
+        self.presences
+            .get(&(device_id, station_id))
+            .map(|presence| presence.online)
+            .unwrap_or(false)
+    }
+
+    /// Check whether a device is currently online on any station.
+    pub fn is_device_online(&self, device_id: i32) -> bool {
+        self.presences
+            .values()
+            .any(|presence| presence.device.id == device_id && presence.online)
+    }
+
+    /// Check whether the device with the given nickname or mac address is online on any station.
+    pub fn is_device_present(&self, name: &str) -> bool {
+        self.presences.values().any(|presence| {
+            presence.online
+                && (presence.device.nickname.as_deref() == Some(name)
+                    || presence.device.mac_address.to_string() == name)
+        })
+    }
+}
+
+/// Create the [Event::DeviceJoined] or [Event::DeviceLeft] for the current presence of a device.
+fn presence_event(presence: &Presence, time: DateTime<Utc>, reason: String) -> Event {
+    if presence.online {
+        Event::DeviceJoined {
+            device: presence.device.clone(),
+            station: presence.station.clone(),
+            time,
+            reason,
+        }
+    } else {
+        Event::DeviceLeft {
+            device: presence.device.clone(),
+            station: presence.station.clone(),
+            time,
+            last_seen: presence.last_seen,
+            reason,
+        }
+    }
+}
+
+/// Remove all times that are older than the start of a window.
+fn prune(times: &mut VecDeque<DateTime<Utc>>, window_start: DateTime<Utc>) {
+    while times.front().is_some_and(|time| *time < window_start) {
+        times.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::events::EventKind;
+
+    fn time(hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 17, hour, minute, second)
+            .unwrap()
+    }
+
+    fn activity(time: DateTime<Utc>) -> Activity {
+        Activity {
+            device: Device {
+                id: 1,
+                mac_address: "02:00:00:00:00:01".parse().unwrap(),
+                nickname: None,
+                description: None,
+                watch: true,
+                vendor: None,
+                quarantined: false,
+            },
+            station: Station {
+                id: 1,
+                mac_address: "02:00:00:00:01:01".parse().unwrap(),
+                ssid: Some("home".into()),
+                channel: 6,
+                power_level: None,
+                nickname: None,
+                description: None,
+                watch: true,
+                protected: Some(true),
+            },
+            time,
+            bytes: 100,
+            rssi: None,
+        }
+    }
+
+    fn tracker(arrival_frames: usize) -> PresenceTracker {
+        let config = config::Presence {
+            absence_timeout: 600,
+            arrival_window: 60,
+            arrival_frames,
+            flap_window: 3600,
+            flap_threshold: 4,
+            ..Default::default()
+        };
+        PresenceTracker::new(&config).unwrap()
+    }
+
+    fn seen(tracker: &mut PresenceTracker, time: DateTime<Utc>) -> Option<EventKind> {
+        tracker
+            .handle_activity(activity(time))
+            .map(|event| event.kind())
+    }
+
+    fn timeouts(tracker: &mut PresenceTracker, time: DateTime<Utc>) -> Vec<EventKind> {
+        tracker
+            .check_timeouts(time)
+            .iter()
+            .map(Event::kind)
+            .collect()
+    }
+
+    #[test]
+    fn arrival_needs_several_frames_within_the_window() {
+        let mut tracker = tracker(3);
+        assert_eq!(seen(&mut tracker, time(12, 0, 0)), None);
+        assert_eq!(seen(&mut tracker, time(12, 1, 30)), None);
+        assert_eq!(seen(&mut tracker, time(12, 3, 0)), None);
+        assert!(!tracker.is_online(1, 1));
+
+        assert_eq!(seen(&mut tracker, time(12, 3, 10)), None);
+        assert_eq!(seen(&mut tracker, time(12, 3, 20)), Some(EventKind::Joined));
+        assert!(tracker.is_online(1, 1));
+
+        // Further frames of a present device don't change anything.
+        assert_eq!(seen(&mut tracker, time(12, 3, 30)), None);
+    }
+
+    #[test]
+    fn departure_after_absence_timeout() {
+        let mut tracker = tracker(1);
+        assert_eq!(seen(&mut tracker, time(12, 0, 0)), Some(EventKind::Joined));
+
+        assert!(timeouts(&mut tracker, time(12, 10, 0)).is_empty());
+        assert_eq!(
+            timeouts(&mut tracker, time(12, 10, 1)),
+            vec![EventKind::Left]
+        );
+        assert!(!tracker.is_online(1, 1));
+    }
+
+    #[test]
+    fn flapping_device_settles_absent() {
+        let mut tracker = tracker(1);
+        assert_eq!(seen(&mut tracker, time(12, 0, 0)), Some(EventKind::Joined));
+        assert_eq!(
+            timeouts(&mut tracker, time(12, 10, 1)),
+            vec![EventKind::Left]
+        );
+        assert_eq!(seen(&mut tracker, time(12, 11, 0)), Some(EventKind::Joined));
+        assert_eq!(
+            timeouts(&mut tracker, time(12, 21, 1)),
+            vec![EventKind::Unstable]
+        );
+        // The departure hasn't been published yet.
+        assert!(tracker.is_online(1, 1));
+
+        assert!(timeouts(&mut tracker, time(13, 21, 0)).is_empty());
+        assert_eq!(
+            timeouts(&mut tracker, time(13, 21, 2)),
+            vec![EventKind::Stable, EventKind::Left]
+        );
+        assert!(!tracker.is_online(1, 1));
+    }
+
+    #[test]
+    fn flapping_device_settles_present() {
+        let mut tracker = tracker(1);
+        assert_eq!(seen(&mut tracker, time(12, 0, 0)), Some(EventKind::Joined));
+        assert_eq!(
+            timeouts(&mut tracker, time(12, 10, 1)),
+            vec![EventKind::Left]
+        );
+        assert_eq!(seen(&mut tracker, time(12, 11, 0)), Some(EventKind::Joined));
+        assert_eq!(
+            timeouts(&mut tracker, time(12, 21, 1)),
+            vec![EventKind::Unstable]
+        );
+
+        // The device is back, but that's suppressed while it's unstable.
+        for minute in (25..60).step_by(5) {
+            assert_eq!(seen(&mut tracker, time(12, minute, 0)), None);
+            assert!(timeouts(&mut tracker, time(12, minute, 1)).is_empty());
+        }
+        for minute in (0..30).step_by(5) {
+            assert_eq!(seen(&mut tracker, time(13, minute, 0)), None);
+        }
+
+        // It has been present the whole time, which is why there's nothing else to publish.
+        assert_eq!(
+            timeouts(&mut tracker, time(13, 25, 1)),
+            vec![EventKind::Stable]
+        );
+        assert!(tracker.is_online(1, 1));
+    }
+}
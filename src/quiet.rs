@@ -0,0 +1,461 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::read_to_string;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, TimeDelta, Utc};
+use log::{error, info};
+use tokio::sync::watch;
+
+use crate::config::{Collector, Restriction, Schedule};
+use crate::db::models::Pause;
+use crate::db::DbPool;
+use crate::events::{device_name, Event};
+use crate::rules::{in_time_window, parse_time};
+
+/// The name of the window, while the collector has been paused via the CLI.
+const PAUSE: &str = "pause";
+
+/// The time in seconds between checks for new pauses.
+const PAUSE_POLL_INTERVAL: u64 = 10;
+
+/// A schedule with its time window already parsed.
+struct ActiveSchedule {
+    schedule: Schedule,
+    after: Option<NaiveTime>,
+    before: Option<NaiveTime>,
+}
+
+/// The reduced mode the collector is currently running in.
+struct Window {
+    name: String,
+    restrictions: Vec<Restriction>,
+    start: DateTime<Utc>,
+    /// Whether a summary should be published, once the window ends.
+    summary: bool,
+    /// The amount of events of each kind during the window.
+    events: BTreeMap<String, usize>,
+    /// The transferred bytes of each device during the window.
+    traffic: BTreeMap<String, i64>,
+}
+
+/// Decides whether the collector runs in a reduced mode right now.
+///
+/// This is either the case during a configured schedule, on holidays or while the collector
+/// has been paused via the CLI. Pauses restrict everything and are always summarized.
+pub struct QuietHours {
+    schedules: Vec<ActiveSchedule>,
+    holidays: HashSet<NaiveDate>,
+    current: Option<Window>,
+}
+
+impl QuietHours {
+    pub fn new(config: &Collector) -> Result<Self> {
+        let mut schedules = Vec::new();
+        for schedule in config.schedules.iter() {
+            schedules.push(ActiveSchedule {
+                schedule: schedule.clone(),
+                after: parse_time(&schedule.after).context(format!(
+                    "Invalid 'after' time in schedule {}",
+                    schedule.name
+                ))?,
+                before: parse_time(&schedule.before).context(format!(
+                    "Invalid 'before' time in schedule {}",
+                    schedule.name
+                ))?,
+            });
+        }
+
+        let holidays = match &config.holidays {
+            Some(path) => load_holidays(path)?,
+            None => HashSet::new(),
+        };
+
+        Ok(QuietHours {
+            schedules,
+            holidays,
+            current: None,
+        })
+    }
+
+    /// Check which window is active right now.
+    /// If a window with a summary just ended, its summary is returned.
+    pub fn update(
+        &mut self,
+        now: DateTime<Utc>,
+        paused_until: Option<DateTime<Utc>>,
+    ) -> Option<Event> {
+        let name = if paused_until.is_some_and(|until| until > now) {
+            Some(PAUSE.to_string())
+        } else {
+            self.active_schedule(now)
+                .map(|active| active.schedule.name.clone())
+        };
+
+        if self.current.as_ref().map(|window| &window.name) == name.as_ref() {
+            return None;
+        }
+
+        let summary = self.current.take().and_then(|window| {
+            info!("Quiet window {} ended", window.name);
+            window.into_summary(now)
+        });
+
+        self.current = name.map(|name| {
+            info!("Quiet window {} started", name);
+            match self.schedule(&name) {
+                Some(schedule) => {
+                    Window::new(name, schedule.restrictions.clone(), schedule.summary, now)
+                }
+                None => Window::new(
+                    name,
+                    vec![
+                        Restriction::NoSweeps,
+                        Restriction::NoNewDevices,
+                        Restriction::NoOutput,
+                    ],
+                    true,
+                    now,
+                ),
+            }
+        });
+
+        summary
+    }
+
+    /// Check whether something is restricted right now.
+    pub fn restricts(&self, restriction: Restriction) -> bool {
+        self.current
+            .as_ref()
+            .is_some_and(|window| window.restrictions.contains(&restriction))
+    }
+
+    /// Count an event for the summary of the current window.
+    pub fn record(&mut self, event: &Event) {
+        let window = match &mut self.current {
+            Some(window) if window.summary && !event.kind().is_diagnostic() => window,
+            _ => return,
+        };
+
+        *window
+            .events
+            .entry(event.kind().as_str().to_string())
+            .or_default() += 1;
+
+        if let Event::Traffic {
+            device,
+            bytes_per_minute,
+            ..
+        } = event
+        {
+            *window.traffic.entry(device_name(device)).or_default() += *bytes_per_minute as i64;
+        }
+    }
+
+    /// Get the first schedule, that's active at the given time.
+    fn active_schedule(&self, now: DateTime<Utc>) -> Option<&ActiveSchedule> {
+        let local = now.with_timezone(&Local);
+        let holiday = self.holidays.contains(&local.date_naive());
+
+        self.schedules.iter().find(|active| {
+            if holiday && active.schedule.holidays {
+                return true;
+            }
+
+            (active.schedule.weekdays.is_empty()
+                || active.schedule.weekdays.contains(&local.weekday()))
+                && in_time_window(local.time(), active.after, active.before)
+        })
+    }
+
+    fn schedule(&self, name: &str) -> Option<&Schedule> {
+        if name == PAUSE {
+            return None;
+        }
+
+        self.schedules
+            .iter()
+            .map(|active| &active.schedule)
+            .find(|schedule| schedule.name == name)
+    }
+}
+
+impl Window {
+    fn new(
+        name: String,
+        restrictions: Vec<Restriction>,
+        summary: bool,
+        start: DateTime<Utc>,
+    ) -> Self {
+        Window {
+            name,
+            restrictions,
+            start,
+            summary,
+            events: BTreeMap::new(),
+            traffic: BTreeMap::new(),
+        }
+    }
+
+    fn into_summary(self, end: DateTime<Utc>) -> Option<Event> {
+        if !self.summary {
+            return None;
+        }
+
+        Some(Event::Summary {
+            window: self.name,
+            start: self.start,
+            time: end,
+            events: self.events,
+            traffic: self.traffic,
+        })
+    }
+}
+
+/// Spawn the task that checks the database for pauses, which have been requested via the CLI.
+/// The receiver always contains the end of the latest pause.
+pub fn spawn_pause_watcher(pool: DbPool) -> watch::Receiver<Option<DateTime<Utc>>> {
+    let (sender, receiver) = watch::channel(None);
+
+    tokio::spawn(async move {
+        loop {
+            let result = match pool.acquire().await {
+                Ok(mut connection) => Pause::latest_until(&mut connection).await,
+                Err(err) => Err(err.into()),
+            };
+            match result {
+                Ok(until) => {
+                    // This only errors if the main loop went away.
+                    if sender.send(until).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => error!("Failed to check for pauses: {:?}", err),
+            }
+
+            tokio::time::sleep(Duration::from_secs(PAUSE_POLL_INTERVAL)).await;
+        }
+    });
+
+    receiver
+}
+
+/// Load all days with an event from an `.ics` calendar file.
+///
+/// Only the start and end dates of events are used, which is all that's needed for holidays.
+/// Multi-day events cover all days from their start until the day before their end.
+fn load_holidays(path: &Path) -> Result<HashSet<NaiveDate>> {
+    let calendar =
+        read_to_string(path).context(format!("Couldn't read holiday file {:?}", path))?;
+
+    let mut holidays = HashSet::new();
+    let mut start = None;
+    let mut end = None;
+    for line in calendar.lines() {
+        let line = line.trim();
+        if line == "BEGIN:VEVENT" {
+            start = None;
+            end = None;
+        } else if line.starts_with("DTSTART") {
+            start = parse_ics_date(line);
+        } else if line.starts_with("DTEND") {
+            end = parse_ics_date(line);
+        } else if line == "END:VEVENT" {
+            let mut day = match start {
+                Some(start) => start,
+                None => continue,
+            };
+            // Events without an end are a single day long.
+            let end = end.unwrap_or(day + TimeDelta::try_days(1).unwrap());
+            loop {
+                holidays.insert(day);
+                day += TimeDelta::try_days(1).unwrap();
+                if day >= end {
+                    break;
+                }
+            }
+        }
+    }
+
+    info!("Loaded {} holidays", holidays.len());
+
+    Ok(holidays)
+}
+
+/// Parse the date of a `DTSTART` or `DTEND` line.
+/// E.g. `DTSTART;VALUE=DATE:20261225` or `DTSTART:20261225T000000Z`.
+fn parse_ics_date(line: &str) -> Option<NaiveDate> {
+    let value = line.split(':').nth(1)?;
+    NaiveDate::parse_from_str(value.get(0..8)?, "%Y%m%d").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::db::models::{Device, Station};
+    use crate::events::EventKind;
+
+    fn schedule(
+        name: &str,
+        after: &str,
+        before: &str,
+        restrictions: Vec<Restriction>,
+        summary: bool,
+    ) -> ActiveSchedule {
+        ActiveSchedule {
+            schedule: Schedule {
+                name: name.into(),
+                after: Some(after.into()),
+                before: Some(before.into()),
+                weekdays: Vec::new(),
+                holidays: false,
+                restrictions,
+                summary,
+            },
+            after: parse_time(&Some(after.into())).unwrap(),
+            before: parse_time(&Some(before.into())).unwrap(),
+        }
+    }
+
+    fn quiet_hours() -> QuietHours {
+        QuietHours {
+            schedules: vec![
+                schedule("night", "22:00", "06:00", vec![Restriction::NoSweeps], true),
+                schedule(
+                    "morning",
+                    "06:00",
+                    "08:00",
+                    vec![Restriction::NoNewDevices],
+                    false,
+                ),
+            ],
+            holidays: HashSet::new(),
+            current: None,
+        }
+    }
+
+    /// Schedules are in local time.
+    fn local(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Local
+            .with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn device() -> Device {
+        Device {
+            id: 1,
+            mac_address: "02:00:00:00:00:01".parse().unwrap(),
+            nickname: Some("phone".into()),
+            description: None,
+            watch: true,
+            vendor: None,
+            quarantined: false,
+        }
+    }
+
+    fn station() -> Station {
+        Station {
+            id: 1,
+            mac_address: "02:00:00:00:01:01".parse().unwrap(),
+            ssid: Some("home".into()),
+            channel: 6,
+            power_level: None,
+            nickname: None,
+            description: None,
+            watch: true,
+            protected: Some(true),
+        }
+    }
+
+    fn joined() -> Event {
+        Event::DeviceJoined {
+            device: device(),
+            station: station(),
+            time: local(17, 23, 0),
+            reason: String::new(),
+        }
+    }
+
+    #[test]
+    fn switches_between_schedules() {
+        let mut quiet = quiet_hours();
+        assert!(quiet.update(local(17, 21, 0), None).is_none());
+        assert!(!quiet.restricts(Restriction::NoSweeps));
+
+        assert!(quiet.update(local(17, 22, 30), None).is_none());
+        assert!(quiet.restricts(Restriction::NoSweeps));
+        assert!(!quiet.restricts(Restriction::NoNewDevices));
+
+        quiet.record(&joined());
+        quiet.record(&Event::Traffic {
+            device: device(),
+            station: station(),
+            time: local(17, 23, 0),
+            bytes_per_minute: 500,
+            last_seen: local(17, 23, 0),
+        });
+        // Diagnostic events aren't summarized.
+        quiet.record(&Event::SweepFinished {
+            time: local(17, 23, 0),
+            watched_channels: vec![6],
+        });
+
+        // The night is over and its summary is published, once the morning starts.
+        match quiet.update(local(18, 6, 0), None) {
+            Some(Event::Summary {
+                window,
+                start,
+                time,
+                events,
+                traffic,
+            }) => {
+                assert_eq!(window, "night");
+                assert_eq!(start, local(17, 22, 30));
+                assert_eq!(time, local(18, 6, 0));
+                assert_eq!(events.len(), 2);
+                assert_eq!(events["joined"], 1);
+                assert_eq!(events["traffic"], 1);
+                assert_eq!(traffic["phone"], 500);
+            }
+            other => panic!("Expected a summary, got {:?}", other),
+        }
+        assert!(!quiet.restricts(Restriction::NoSweeps));
+        assert!(quiet.restricts(Restriction::NoNewDevices));
+
+        // The morning doesn't have a summary.
+        quiet.record(&joined());
+        assert!(quiet.update(local(18, 8, 0), None).is_none());
+        assert!(!quiet.restricts(Restriction::NoNewDevices));
+    }
+
+    #[test]
+    fn pauses_override_schedules() {
+        let mut quiet = quiet_hours();
+        assert!(quiet.update(local(17, 22, 30), None).is_none());
+
+        // The night ends early, since it's replaced by the pause.
+        let until = Some(local(17, 23, 30));
+        let summary = quiet.update(local(17, 23, 0), until);
+        assert_eq!(summary.map(|event| event.kind()), Some(EventKind::Summary));
+        assert!(quiet.restricts(Restriction::NoSweeps));
+        assert!(quiet.restricts(Restriction::NoNewDevices));
+        assert!(quiet.restricts(Restriction::NoOutput));
+        assert!(quiet.update(local(17, 23, 15), until).is_none());
+
+        // Pauses are always summarized. Afterwards, the night continues.
+        quiet.record(&joined());
+        match quiet.update(local(17, 23, 30), until) {
+            Some(Event::Summary { window, events, .. }) => {
+                assert_eq!(window, PAUSE);
+                assert_eq!(events["joined"], 1);
+            }
+            other => panic!("Expected a summary, got {:?}", other),
+        }
+        assert!(quiet.restricts(Restriction::NoSweeps));
+        assert!(!quiet.restricts(Restriction::NoOutput));
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::Utc;
+use log::{error, warn};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::task::JoinHandle;
+
+use crate::db::models::*;
+use crate::db::DbPool;
+use crate::events::Event;
+use crate::presence::Activity;
+
+/// The station a device is currently attached to.
+struct Current {
+    station: Station,
+    /// Another station, that the device might be roaming to, with its amount of frames in a row.
+    candidate: Option<(i32, usize)>,
+}
+
+/// Tracks which station each device is currently attached to.
+///
+/// Devices in mesh networks switch between stations. Some frames might still be exchanged with
+/// the previous station during the switch, which is why a device only counts as roamed once
+/// `min_frames` frames in a row have been seen on the new station.
+pub struct RoamingTracker {
+    min_frames: usize,
+    /// The current station of each device by its id.
+    current: HashMap<i32, Current>,
+}
+
+impl RoamingTracker {
+    pub fn new(min_frames: usize) -> Self {
+        RoamingTracker {
+            min_frames,
+            current: HashMap::new(),
+        }
+    }
+
+    /// Update the current station of a device.
+    /// If the device switched to another station, a [Event::DeviceRoamed] is returned.
+    pub fn handle_activity(&mut self, activity: &Activity) -> Option<Event> {
+        let current = match self.current.get_mut(&activity.device.id) {
+            Some(current) => current,
+            None => {
+                self.current.insert(
+                    activity.device.id,
+                    Current {
+                        station: activity.station.clone(),
+                        candidate: None,
+                    },
+                );
+                return None;
+            }
+        };
+
+        if current.station.id == activity.station.id {
+            current.station = activity.station.clone();
+            current.candidate = None;
+            return None;
+        }
+
+        let frames = match current.candidate {
+            Some((station_id, frames)) if station_id == activity.station.id => frames + 1,
+            _ => 1,
+        };
+        if frames < self.min_frames {
+            current.candidate = Some((activity.station.id, frames));
+            return None;
+        }
+
+        let from = std::mem::replace(&mut current.station, activity.station.clone());
+        current.candidate = None;
+
+        Some(Event::DeviceRoamed {
+            device: activity.device.clone(),
+            from,
+            to: activity.station.clone(),
+            time: activity.time,
+        })
+    }
+
+    /// Devices that left their current station aren't attached to any station anymore.
+    pub fn handle_event(&mut self, event: &Event) {
+        if let Event::DeviceLeft {
+            device, station, ..
+        } = event
+        {
+            if self
+                .current
+                .get(&device.id)
+                .is_some_and(|current| current.station.id == station.id)
+            {
+                self.current.remove(&device.id);
+            }
+        }
+    }
+}
+
+/// Spawn the task that writes the attachment history of all devices into the
+/// `device_attachments` table.
+///
+/// A device gets attached to the first station it joins, switches stations when it roams and
+/// gets detached once it leaves its current station.
+pub fn spawn_attachment_log(pool: DbPool, mut receiver: Receiver<Event>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        // The station each device is currently attached to.
+        let mut attached: HashMap<i32, i32> = HashMap::new();
+
+        // Attachments of the previous run have ended at some unknown point in time.
+        match pool.acquire().await {
+            Ok(mut connection) => {
+                if let Err(err) = Attachment::detach_all(&mut connection, Utc::now()).await {
+                    error!("Failed to close previous attachments: {:?}", err);
+                }
+            }
+            Err(err) => error!("Failed to close previous attachments: {:?}", err),
+        }
+
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(count)) => {
+                    warn!(
+                        "Attachment log couldn't keep up and missed {} events",
+                        count
+                    );
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            if let Err(err) = log_attachment(&pool, &mut attached, &event).await {
+                error!("Failed to write device attachment: {:?}", err);
+            }
+        }
+    })
+}
+
+async fn log_attachment(
+    pool: &DbPool,
+    attached: &mut HashMap<i32, i32>,
+    event: &Event,
+) -> Result<()> {
+    match event {
+        Event::DeviceJoined {
+            device,
+            station,
+            time,
+            ..
+        } => {
+            if attached.contains_key(&device.id) {
+                return Ok(());
+            }
+            attached.insert(device.id, station.id);
+
+            let mut connection = pool.acquire().await?;
+            Attachment::attach(&mut connection, device.id, station.id, *time).await
+        }
+        Event::DeviceRoamed {
+            device,
+            from,
+            to,
+            time,
+        } => {
+            attached.insert(device.id, to.id);
+
+            let mut connection = pool.acquire().await?;
+            Attachment::detach(&mut connection, device.id, from.id, *time).await?;
+            Attachment::attach(&mut connection, device.id, to.id, *time).await
+        }
+        Event::DeviceLeft {
+            device,
+            station,
+            time,
+            ..
+        } => {
+            if attached.get(&device.id) != Some(&station.id) {
+                return Ok(());
+            }
+            attached.remove(&device.id);
+
+            let mut connection = pool.acquire().await?;
+            Attachment::detach(&mut connection, device.id, station.id, *time).await
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeDelta, Utc};
+use log::{debug, info};
+
+use crate::config::Rule;
+use crate::events::Event;
+use crate::groups::GroupTracker;
+use crate::presence::PresenceTracker;
+use crate::supervisor::Supervisor;
+
+/// A rule with its time window and delay already parsed.
+struct ActiveRule {
+    rule: Rule,
+    after: Option<NaiveTime>,
+    before: Option<NaiveTime>,
+    delay: Option<TimeDelta>,
+}
+
+/// A rule that has been triggered, but is waiting for its delay to pass.
+struct PendingRule {
+    /// The index of the rule in [RuleEngine::rules].
+    rule: usize,
+    event: Event,
+    due: DateTime<Utc>,
+}
+
+/// Evaluates the conditions of all rules, whenever a matching event happens.
+pub struct RuleEngine {
+    rules: Vec<ActiveRule>,
+    pending: Vec<PendingRule>,
+    supervisor: Supervisor,
+}
+
+impl RuleEngine {
+    pub fn new(rules: &[Rule], supervisor: Supervisor) -> Result<Self> {
+        let mut active_rules = Vec::new();
+        for rule in rules {
+            let delay = match rule.delay {
+                Some(delay) if delay > 0 => Some(
+                    TimeDelta::try_seconds(delay)
+                        .context(format!("Invalid delay in rule {}", rule.name))?,
+                ),
+                _ => None,
+            };
+            active_rules.push(ActiveRule {
+                rule: rule.clone(),
+                after: parse_time(&rule.after)
+                    .context(format!("Invalid 'after' time in rule {}", rule.name))?,
+                before: parse_time(&rule.before)
+                    .context(format!("Invalid 'before' time in rule {}", rule.name))?,
+                delay,
+            });
+        }
+
+        Ok(RuleEngine {
+            rules: active_rules,
+            pending: Vec::new(),
+            supervisor,
+        })
+    }
+
+    /// Check all rules that match this event.
+    /// Rules without a delay are fired right away, all others are scheduled.
+    /// Delays start at `now`, which follows the replayed frames during a replay.
+    pub fn handle_event(&mut self, event: &Event, presence: &PresenceTracker, now: DateTime<Utc>) {
+        for (index, active) in self.rules.iter().enumerate() {
+            if !active.rule.filter.matches(event) {
+                continue;
+            }
+
+            let delay = match active.delay {
+                Some(delay) => delay,
+                None => {
+                    active.fire(&self.supervisor, event, presence, now);
+                    continue;
+                }
+            };
+
+            // A newer event of the same device replaces the old one.
+            // E.g. if a device left, came back and left again, the delay starts anew.
+            self.pending
+                .retain(|pending| pending.rule != index || !same_subject(&pending.event, event));
+            debug!(
+                "Rule {} is waiting {} seconds before firing",
+                active.rule.name,
+                delay.num_seconds()
+            );
+            self.pending.push(PendingRule {
+                rule: index,
+                event: event.clone(),
+                due: now + delay,
+            });
+        }
+    }
+
+    /// Fire all delayed rules, whose delay passed and whose device or group is still in the same state.
+    /// While the output is muted, rules that are due are dropped instead.
+    pub fn check_pending(
+        &mut self,
+        now: DateTime<Utc>,
+        muted: bool,
+        presence: &PresenceTracker,
+        groups: &GroupTracker,
+    ) {
+        let (due, pending): (Vec<PendingRule>, Vec<PendingRule>) = self
+            .pending
+            .drain(..)
+            .partition(|pending| pending.due <= now);
+        self.pending = pending;
+
+        for pending in due {
+            let active = &self.rules[pending.rule];
+            if muted {
+                debug!("Dropping rule {}, output is muted", active.rule.name);
+                continue;
+            }
+
+            let still_valid = match &pending.event {
+                Event::DeviceJoined {
+                    device, station, ..
+                } => presence.is_online(device.id, station.id),
+                Event::DeviceLeft {
+                    device, station, ..
+                } => !presence.is_online(device.id, station.id),
+                Event::GroupArrived { group, .. } => groups.is_arrived(group.id),
+                Event::GroupLeft { group, .. } => !groups.is_arrived(group.id),
+                Event::GroupComplete { group, .. } => groups.is_complete(group.id),
+                _ => true,
+            };
+
+            if !still_valid {
+                debug!(
+                    "Dropping rule {}, device or group changed its state",
+                    active.rule.name
+                );
+                continue;
+            }
+
+            active.fire(&self.supervisor, &pending.event, presence, now);
+        }
+    }
+}
+
+impl ActiveRule {
+    /// Execute the rule's command, if all conditions are met.
+    fn fire(
+        &self,
+        supervisor: &Supervisor,
+        event: &Event,
+        presence: &PresenceTracker,
+        now: DateTime<Utc>,
+    ) {
+        if !self.conditions_met(presence, now) {
+            debug!("Conditions of rule {} aren't met", self.rule.name);
+            return;
+        }
+
+        info!("Firing rule {}", self.rule.name);
+        supervisor.run(
+            &self.rule.name,
+            &self.rule.command,
+            self.rule.timeout,
+            event,
+        );
+    }
+
+    fn conditions_met(&self, presence: &PresenceTracker, now: DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&Local);
+
+        if !self.rule.weekdays.is_empty() && !self.rule.weekdays.contains(&local.weekday()) {
+            return false;
+        }
+
+        if !in_time_window(local.time(), self.after, self.before) {
+            return false;
+        }
+
+        if !self
+            .rule
+            .present
+            .iter()
+            .all(|name| presence.is_device_present(name))
+        {
+            return false;
+        }
+
+        !self
+            .rule
+            .absent
+            .iter()
+            .any(|name| presence.is_device_present(name))
+    }
+}
+
+/// Check whether a time is inside a time window.
+/// If `after` is later than `before`, the window wraps around midnight.
+pub fn in_time_window(
+    time: NaiveTime,
+    after: Option<NaiveTime>,
+    before: Option<NaiveTime>,
+) -> bool {
+    match (after, before) {
+        (Some(after), Some(before)) if after > before => time >= after || time < before,
+        _ => {
+            after.map(|after| time >= after).unwrap_or(true)
+                && before.map(|before| time < before).unwrap_or(true)
+        }
+    }
+}
+
+/// Check whether two events are about the same device on the same station.
+fn same_subject(first: &Event, second: &Event) -> bool {
+    first.device().map(|device| device.id) == second.device().map(|device| device.id)
+        && first.station().map(|station| station.id) == second.station().map(|station| station.id)
+}
+
+pub fn parse_time(time: &Option<String>) -> Result<Option<NaiveTime>> {
+    match time {
+        Some(time) => Ok(Some(NaiveTime::parse_from_str(time, "%H:%M")?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn time_window_during_the_day() {
+        let (after, before) = (Some(time(8, 0)), Some(time(17, 0)));
+        assert!(in_time_window(time(8, 0), after, before));
+        assert!(in_time_window(time(12, 0), after, before));
+        assert!(!in_time_window(time(17, 0), after, before));
+        assert!(!in_time_window(time(7, 59), after, before));
+    }
+
+    #[test]
+    fn time_window_across_midnight() {
+        let (after, before) = (Some(time(22, 0)), Some(time(6, 0)));
+        assert!(in_time_window(time(22, 0), after, before));
+        assert!(in_time_window(time(23, 59), after, before));
+        assert!(in_time_window(time(0, 0), after, before));
+        assert!(in_time_window(time(5, 59), after, before));
+        assert!(!in_time_window(time(6, 0), after, before));
+        assert!(!in_time_window(time(12, 0), after, before));
+    }
+
+    #[test]
+    fn open_time_windows() {
+        assert!(in_time_window(time(3, 0), None, None));
+        assert!(in_time_window(time(23, 0), Some(time(22, 0)), None));
+        assert!(!in_time_window(time(21, 0), Some(time(22, 0)), None));
+        assert!(in_time_window(time(5, 0), None, Some(time(6, 0))));
+        assert!(!in_time_window(time(7, 0), None, Some(time(6, 0))));
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::fs::{read_dir, read_to_string};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::{debug, error, info, warn};
+use rhai::serde::to_dynamic;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use tokio::runtime::Handle;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+
+use crate::config::Config;
+use crate::db::models::*;
+use crate::db::DbPool;
+use crate::events::{device_name, station_name, Event};
+use crate::supervisor::{shell, Supervisor};
+
+/// The function each script has to define, which is called for every event.
+const EVENT_HANDLER: &str = "on_event";
+
+/// A compiled script from the script directory.
+struct Script {
+    name: String,
+    ast: AST,
+}
+
+/// Runs user-defined Rhai scripts for every event.
+///
+/// Each script defines an `on_event(event)` function, which gets the event as a map.
+/// Scripts run on their own thread, one event after another, so they never block the collector.
+pub struct ScriptRunner {
+    engine: Engine,
+    scripts: Vec<Script>,
+    /// The timeout for shell commands and HTTP requests.
+    timeout: Duration,
+}
+
+impl ScriptRunner {
+    /// Compile all `.rhai` files in the script directory.
+    /// Returns `None`, if there aren't any scripts.
+    pub fn load(config: &Config) -> Result<Option<Self>> {
+        let directory = config.get_script_dir()?;
+        if !directory.exists() {
+            debug!("Script directory {:?} doesn't exist", directory);
+            return Ok(None);
+        }
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(config.scripts.max_operations);
+        engine.on_print(|text| info!("{}", text));
+
+        let mut paths = Vec::new();
+        for entry in read_dir(&directory)
+            .context(format!("Couldn't read script directory {:?}", directory))?
+        {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "rhai")
+            {
+                paths.push(path);
+            }
+        }
+        // Scripts are executed in alphabetical order.
+        paths.sort();
+
+        let mut scripts = Vec::new();
+        for path in paths {
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let source =
+                read_to_string(&path).context(format!("Couldn't read script {:?}", path))?;
+            let ast = engine
+                .compile(source)
+                .context(format!("Failed to compile script {}", name))?;
+
+            let has_handler = ast
+                .iter_functions()
+                .any(|function| function.name == EVENT_HANDLER && function.params.len() == 1);
+            if !has_handler {
+                warn!(
+                    "Script {} doesn't define {}(event), skipping",
+                    name, EVENT_HANDLER
+                );
+                continue;
+            }
+
+            info!("Loaded script {}", name);
+            scripts.push(Script { name, ast });
+        }
+
+        if scripts.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(ScriptRunner {
+            engine,
+            scripts,
+            timeout: Duration::from_secs(config.scripts.timeout),
+        }))
+    }
+
+    /// Register all helper functions, which scripts can use.
+    ///
+    /// Devices and stations are loaded from the database on each call, so scripts also see
+    /// devices and stations, that have been discovered after startup.
+    /// Shell commands are executed by the supervisor, just like hooks.
+    fn register_helpers(&mut self, pool: DbPool, supervisor: Supervisor) {
+        let (pool_clone, handle) = (pool.clone(), Handle::current());
+        self.engine
+            .register_fn("devices", move || -> Result<Array, Box<EvalAltResult>> {
+                let devices = handle.block_on(load_devices(&pool_clone))?;
+                Ok(devices.into_values().collect())
+            });
+        let (pool_clone, handle) = (pool.clone(), Handle::current());
+        self.engine.register_fn(
+            "device",
+            move |name: &str| -> Result<Dynamic, Box<EvalAltResult>> {
+                let devices = handle.block_on(load_devices(&pool_clone))?;
+                Ok(lookup(&devices, name))
+            },
+        );
+
+        let (pool_clone, handle) = (pool.clone(), Handle::current());
+        self.engine
+            .register_fn("stations", move || -> Result<Array, Box<EvalAltResult>> {
+                let stations = handle.block_on(load_stations(&pool_clone))?;
+                Ok(stations.into_values().collect())
+            });
+        let handle = Handle::current();
+        self.engine.register_fn(
+            "station",
+            move |name: &str| -> Result<Dynamic, Box<EvalAltResult>> {
+                let stations = handle.block_on(load_stations(&pool))?;
+                Ok(lookup(&stations, name))
+            },
+        );
+
+        let timeout = self.timeout;
+        let handle = Handle::current();
+        self.engine.register_fn(
+            "shell",
+            move |command: &str| -> Result<Map, Box<EvalAltResult>> {
+                handle.block_on(run_shell(&supervisor, command, timeout))
+            },
+        );
+
+        let client = reqwest::Client::new();
+        let handle = Handle::current();
+        self.engine.register_fn(
+            "http_get",
+            move |url: &str| -> Result<String, Box<EvalAltResult>> {
+                let request = client.get(url).timeout(timeout);
+                handle.block_on(send_request(request))
+            },
+        );
+
+        let client = reqwest::Client::new();
+        let handle = Handle::current();
+        self.engine.register_fn(
+            "http_post",
+            move |url: &str, body: &str| -> Result<String, Box<EvalAltResult>> {
+                let request = client.post(url).body(body.to_string()).timeout(timeout);
+                handle.block_on(send_request(request))
+            },
+        );
+    }
+
+    /// Call the event handler of all scripts.
+    /// Failing scripts are logged and don't affect other scripts.
+    fn handle_event(&self, event: &Event) {
+        let event = match to_dynamic(event) {
+            Ok(event) => event,
+            Err(err) => {
+                error!("Failed to pass event to scripts: {}", err);
+                return;
+            }
+        };
+
+        for script in self.scripts.iter() {
+            let mut scope = Scope::new();
+            let result = self.engine.call_fn::<Dynamic>(
+                &mut scope,
+                &script.ast,
+                EVENT_HANDLER,
+                (event.clone(),),
+            );
+            if let Err(err) = result {
+                error!("Script {} failed: {}", script.name, err);
+            }
+        }
+    }
+}
+
+/// Spawn the thread that runs all scripts for incoming events.
+/// This has to be called from within the tokio runtime.
+pub fn spawn_script_runner(
+    mut runner: ScriptRunner,
+    pool: DbPool,
+    supervisor: Supervisor,
+    mut receiver: Receiver<Event>,
+) -> std::thread::JoinHandle<()> {
+    runner.register_helpers(pool, supervisor);
+
+    // Scripts are synchronous and may take a while, which is why they get their own thread.
+    std::thread::spawn(move || loop {
+        let event = match receiver.blocking_recv() {
+            Ok(event) => event,
+            Err(RecvError::Lagged(count)) => {
+                warn!("Scripts couldn't keep up and missed {} events", count);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        // Scripts can't select events, which is why they don't get the diagnostic ones.
+        if event.kind().is_diagnostic() {
+            continue;
+        }
+
+        runner.handle_event(&event);
+    })
+}
+
+/// Convert models to script maps, keyed by their name.
+fn to_maps<'a, T: serde::Serialize + 'a>(
+    models: impl Iterator<Item = &'a T>,
+    name: impl Fn(&T) -> String,
+) -> HashMap<String, Dynamic> {
+    models
+        .filter_map(|model| Some((name(model), to_dynamic(model).ok()?)))
+        .collect()
+}
+
+/// Load all devices as script maps, keyed by their name.
+async fn load_devices(pool: &DbPool) -> Result<HashMap<String, Dynamic>, Box<EvalAltResult>> {
+    let mut connection = pool.acquire().await.map_err(|err| err.to_string())?;
+    let devices = Device::known_devices(&mut connection)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(to_maps(devices.values(), device_name))
+}
+
+/// Load all stations as script maps, keyed by their name.
+async fn load_stations(pool: &DbPool) -> Result<HashMap<String, Dynamic>, Box<EvalAltResult>> {
+    let mut connection = pool.acquire().await.map_err(|err| err.to_string())?;
+    let stations = Station::known_stations(&mut connection)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(to_maps(stations.values(), station_name))
+}
+
+/// Find a device or station by its name or mac address.
+/// Returns `()`, if there's no such device or station.
+fn lookup(models: &HashMap<String, Dynamic>, name: &str) -> Dynamic {
+    if let Some(model) = models.get(name) {
+        return model.clone();
+    }
+
+    models
+        .values()
+        .find(|model| {
+            model
+                .read_lock::<Map>()
+                .and_then(|map| map.get("mac_address").map(|mac| mac.to_string() == name))
+                .unwrap_or(false)
+        })
+        .cloned()
+        .unwrap_or(Dynamic::UNIT)
+}
+
+/// Execute a command via `sh -c` and return its exit code and output.
+async fn run_shell(
+    supervisor: &Supervisor,
+    command: &str,
+    timeout: Duration,
+) -> Result<Map, Box<EvalAltResult>> {
+    let output = supervisor.execute(shell(command), timeout).await;
+    if output.timed_out {
+        return Err(format!("Command {} timed out", command).into());
+    }
+
+    let mut result = Map::new();
+    result.insert(
+        "code".into(),
+        Dynamic::from(output.exit_code.unwrap_or(-1) as i64),
+    );
+    result.insert("stdout".into(), output.stdout.into());
+    result.insert("stderr".into(), output.stderr.into());
+
+    Ok(result)
+}
+
+/// Send a HTTP request and return the body of the response.
+/// Responses with an error status fail the script.
+async fn send_request(request: reqwest::RequestBuilder) -> Result<String, Box<EvalAltResult>> {
+    let response = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.to_string())?;
+
+    response.text().await.map_err(|err| err.to_string().into())
+}
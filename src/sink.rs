@@ -0,0 +1,37 @@
+use log::warn;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::task::JoinHandle;
+
+use crate::events::Event;
+
+/// Receives the events of an embedded collector in-process.
+///
+/// Sinks are registered via [crate::Daemon::add_sink] and run in their own task.
+pub trait EventSink: Send + 'static {
+    /// Called for each event in the order they have been published.
+    ///
+    /// Slow sinks don't block the collector, but they'll miss events, once they fall too far behind.
+    fn handle_event(&mut self, event: &Event);
+}
+
+/// Spawn the task that passes all incoming events to a sink.
+pub(crate) fn spawn_sink(
+    mut sink: impl EventSink,
+    mut receiver: Receiver<Event>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(count)) => {
+                    warn!("Event sink couldn't keep up and missed {} events", count);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            sink.handle_event(&event);
+        }
+    })
+}
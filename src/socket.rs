@@ -0,0 +1,96 @@
+use std::fs::remove_file;
+
+use anyhow::{Context, Result};
+use log::{debug, error, info, warn};
+use serde_derive::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::task::JoinHandle;
+
+use crate::config::Socket;
+use crate::events::{Event, EventBus};
+
+/// The version of the schema of streamed events.
+/// This is increased with every incompatible change of the event format.
+const SCHEMA_VERSION: u32 = 1;
+
+/// A single line in the event stream.
+#[derive(Serialize)]
+struct Message<'a> {
+    version: u32,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Create the Unix socket and spawn the task that accepts clients.
+///
+/// Each client gets every event as a line of JSON, starting with the events after it connected.
+/// The stream isn't affected by quiet windows, since it's only used by local tools.
+/// Returns the task accepting new clients, which keeps the event bus around.
+pub fn spawn_event_socket(config: &Socket, events: EventBus) -> Result<JoinHandle<()>> {
+    // The socket of a previous run would prevent us from binding.
+    if config.path.exists() {
+        remove_file(&config.path).context(format!(
+            "Couldn't remove old event socket {:?}",
+            config.path
+        ))?;
+    }
+    let listener = UnixListener::bind(&config.path)
+        .context(format!("Couldn't create event socket {:?}", config.path))?;
+    info!("Streaming events on {:?}", config.path);
+
+    let task = tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    error!("Failed to accept event socket client: {:?}", err);
+                    continue;
+                }
+            };
+
+            debug!("Event socket client connected");
+            tokio::spawn(stream_events(stream, events.subscribe_unmuted()));
+        }
+    });
+
+    Ok(task)
+}
+
+/// Write all events to a client, until it disconnects.
+async fn stream_events(mut stream: UnixStream, mut receiver: Receiver<Event>) {
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(count)) => {
+                warn!(
+                    "Event socket client couldn't keep up and missed {} events",
+                    count
+                );
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        let message = Message {
+            version: SCHEMA_VERSION,
+            event: &event,
+        };
+        let mut line = match serde_json::to_string(&message) {
+            Ok(line) => line,
+            Err(err) => {
+                error!("Failed to serialize event for event socket: {:?}", err);
+                continue;
+            }
+        };
+        line.push('\n');
+
+        // Clients simply disconnect, once they aren't interested anymore.
+        if stream.write_all(line.as_bytes()).await.is_err() {
+            debug!("Event socket client disconnected");
+            return;
+        }
+    }
+}
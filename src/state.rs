@@ -1,13 +1,23 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use anyhow::Result;
 use chrono::TimeDelta;
 use chrono::{DateTime, Utc};
 use log::info;
 
-use crate::config::Config;
+use crate::activity::ActivityTracker;
+use crate::config::{Config, Restriction};
 use crate::db::models::*;
 use crate::db::DbPool;
+use crate::events::{Event, EventBus};
+use crate::groups::GroupTracker;
+use crate::presence::PresenceTracker;
+use crate::quiet::QuietHours;
+use crate::roaming::RoamingTracker;
+use crate::rules::RuleEngine;
+use crate::supervisor::Supervisor;
+use crate::traffic::TrafficAggregator;
+use crate::zones::ZoneTracker;
 
 pub struct AppState {
     /// The current configuration
@@ -19,6 +29,27 @@ pub struct AppState {
     pub devices: HashMap<String, Device>,
     pub station_device_map: HashMap<i32, HashSet<i32>>,
 
+    /// The current presence of all devices that have been seen on watched stations.
+    pub presence: PresenceTracker,
+    /// The station each device is currently attached to.
+    pub roaming: RoamingTracker,
+    /// The presence of all device groups.
+    pub groups: GroupTracker,
+    /// The traffic of all active devices during the current minute.
+    pub traffic: TrafficAggregator,
+    /// Whether devices are active or idle, based on their traffic.
+    pub activity: ActivityTracker,
+    /// The proximity zones of devices, based on their signal strength.
+    pub zones: ZoneTracker,
+    /// Used to notify all subscribers about any events.
+    pub events: EventBus,
+    /// Executes all rules whose conditions are met.
+    pub rules: RuleEngine,
+    /// Runs the commands of hooks and rules.
+    pub supervisor: Supervisor,
+    /// The schedules during which the collector runs in a reduced mode.
+    pub quiet: QuietHours,
+
     /// The list of channels that are currently being scanned.
     pub watched_channels: Vec<i32>,
     /// Since the list of watched channels updates we cannot create a long-running iterator
@@ -33,7 +64,7 @@ pub struct AppState {
 }
 
 impl AppState {
-    pub fn new() -> Result<Self> {
+    pub fn new(config: Config, supervisor: Supervisor) -> Result<Self> {
         // Set the last channel sweep and switch to the past.
         // That way we start with a sweep right away.
         let last_full_sweep = Utc::now();
@@ -41,12 +72,29 @@ impl AppState {
             .checked_sub_signed(TimeDelta::try_hours(2).unwrap())
             .expect("This should happen.");
 
+        let presence = PresenceTracker::new(&config.presence)?;
+        let roaming = RoamingTracker::new(config.presence.roaming_frames);
+        let rules = RuleEngine::new(&config.rules, supervisor.clone())?;
+        let activity = ActivityTracker::new(&config.activities)?;
+        let zones = ZoneTracker::new(&config.proximity);
+        let quiet = QuietHours::new(&config.collector)?;
+
         let mut state = AppState {
-            config: Config::new()?,
+            config,
 
             stations: HashMap::new(),
             devices: HashMap::new(),
             station_device_map: HashMap::new(),
+            presence,
+            roaming,
+            groups: GroupTracker::default(),
+            traffic: TrafficAggregator::default(),
+            activity,
+            zones,
+            events: EventBus::default(),
+            rules,
+            supervisor,
+            quiet,
             watched_channels: Vec::new(),
             current_watched_channels: 0,
             last_full_sweep,
@@ -75,14 +123,72 @@ impl AppState {
         self.stations = Station::known_stations(&mut connection).await?;
         self.devices = Device::known_devices(&mut connection).await?;
         self.station_device_map = DeviceStation::get_station_device_map(&mut connection).await?;
+        self.groups
+            .init(&mut connection, &self.config.groups, &self.devices)
+            .await?;
         self.update_watched_channels(supported_channels);
         info!("Watching channels: {:?}", self.watched_channels);
 
         Ok(())
     }
 
+    /// Reload the station cache, e.g. after a full sweep found new stations.
+    /// This also picks up stations, that have been (un)watched in the meantime.
+    pub async fn reload_stations(&mut self, pool: &DbPool) -> Result<()> {
+        let mut connection = pool.acquire().await?;
+        self.stations = Station::known_stations(&mut connection).await?;
+        self.station_device_map = DeviceStation::get_station_device_map(&mut connection).await?;
+
+        Ok(())
+    }
+
+    /// The bssids of all watched stations in lower case.
+    pub fn watched_bssids(&self) -> BTreeSet<String> {
+        self.stations
+            .values()
+            .filter(|station| station.watch)
+            .map(|station| station.mac_address.to_string().to_lowercase())
+            .collect()
+    }
+
+    /// Publish an event to all subscribers and let the rule engine react to it.
+    /// Any group, activity and zone events that result from it are published as well.
+    pub fn publish(&mut self, event: Event) {
+        self.roaming.handle_event(&event);
+        let mut derived_events = self.groups.handle_event(&event, &self.presence);
+        derived_events.extend(self.activity.handle_event(&event));
+        derived_events.extend(self.zones.handle_event(&event));
+
+        for event in std::iter::once(event).chain(derived_events) {
+            self.quiet.record(&event);
+            // Rules execute commands, which is output as well.
+            if !self.events.is_muted() {
+                self.rules
+                    .handle_event(&event, &self.presence, event.time());
+            }
+            self.events.publish(event);
+        }
+    }
+
+    /// Check whether a quiet window is active at `now` and apply its restrictions.
+    /// If a window ended, its summary is published.
+    pub fn update_quiet_hours(&mut self, now: DateTime<Utc>, paused_until: Option<DateTime<Utc>>) {
+        let summary = self.quiet.update(now, paused_until);
+        self.events
+            .set_muted(self.quiet.restricts(Restriction::NoOutput));
+
+        if let Some(summary) = summary {
+            self.publish(summary);
+        }
+    }
+
     /// Returns, whether it's time to do the next full sweep.
+    /// Sweeps are postponed, while a quiet window prohibits them.
     pub fn should_sweep(&self) -> bool {
+        if self.quiet.restricts(Restriction::NoSweeps) {
+            return false;
+        }
+
         (Utc::now() - self.last_full_sweep)
             > TimeDelta::try_seconds(self.config.collector.time_between_sweeps).unwrap()
     }
@@ -95,8 +201,9 @@ impl AppState {
     }
 
     /// Convenience method to check, whether it's time to switch to the next channel.
-    pub fn should_switch_channel(&self) -> bool {
-        if self.should_sweep() {
+    /// Channels are switched faster, while the device is sweeping.
+    pub fn should_switch_channel(&self, sweeping: bool) -> bool {
+        if sweeping {
             (Utc::now() - self.last_channel_switch)
                 > TimeDelta::try_milliseconds(self.config.collector.sweep_channel_switch_timeout)
                     .unwrap()
@@ -0,0 +1,295 @@
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, error, warn};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::config::Hooks;
+use crate::db::models::HookExecution;
+use crate::db::DbPool;
+use crate::events::Event;
+
+/// The result of a supervised command.
+#[derive(Default)]
+pub struct CommandOutput {
+    pub started_at: DateTime<Utc>,
+    pub duration: Duration,
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Executes the commands of hooks and rules in the background.
+///
+/// Commands never block the caller. At most `max_concurrent` commands run at the same time,
+/// commands that exceed their timeout are killed and every execution is recorded in the
+/// `hook_executions` table.
+///
+/// Each command runs in its own process group, so that everything it started is killed on
+/// timeout, e.g. all parts of a pipe.
+#[derive(Clone)]
+pub struct Supervisor {
+    pool: DbPool,
+    semaphore: Arc<Semaphore>,
+    max_concurrent: u32,
+    /// The timeout in seconds for commands that don't specify their own.
+    default_timeout: u64,
+    /// The maximum amount of bytes of stdout/stderr that's stored for each execution.
+    output_limit: usize,
+}
+
+impl Supervisor {
+    pub fn new(config: &Hooks, pool: DbPool) -> Result<Self> {
+        // No command would ever be allowed to run.
+        if config.max_concurrent == 0 {
+            bail!("The hook setting 'max_concurrent' has to be at least 1");
+        }
+
+        Ok(Supervisor {
+            pool,
+            semaphore: Arc::new(Semaphore::new(config.max_concurrent)),
+            max_concurrent: config.max_concurrent as u32,
+            default_timeout: config.timeout,
+            output_limit: config.output_limit,
+        })
+    }
+
+    /// Execute a command via `sh -c` in the background.
+    /// All information about the event is passed to the command via environment variables.
+    pub fn run(&self, name: &str, hook_command: &str, timeout: Option<u64>, event: &Event) {
+        let mut command = shell(hook_command);
+        command
+            .env("WIFITIFY_EVENT", event.kind().as_str())
+            .env("WIFITIFY_TIME", event.time().to_rfc3339());
+
+        if let Some(device) = event.device() {
+            command
+                .env("WIFITIFY_DEVICE_MAC", device.mac_address.to_string())
+                .env(
+                    "WIFITIFY_DEVICE_NICKNAME",
+                    device.nickname.clone().unwrap_or_default(),
+                )
+                .env(
+                    "WIFITIFY_DEVICE_DESCRIPTION",
+                    device.description.clone().unwrap_or_default(),
+                )
+                .env(
+                    "WIFITIFY_DEVICE_VENDOR",
+                    device.vendor.clone().unwrap_or_default(),
+                );
+        }
+
+        if let Some(station) = event.station() {
+            command
+                .env("WIFITIFY_STATION_BSSID", station.mac_address.to_string())
+                .env(
+                    "WIFITIFY_STATION_SSID",
+                    station.ssid.clone().unwrap_or_default(),
+                )
+                .env(
+                    "WIFITIFY_STATION_NICKNAME",
+                    station.nickname.clone().unwrap_or_default(),
+                );
+        }
+
+        let mut execution = HookExecution {
+            id: 0,
+            name: name.to_string(),
+            command: hook_command.to_string(),
+            event: event.kind().as_str().to_string(),
+            started_at: Utc::now(),
+            duration_ms: 0,
+            exit_code: None,
+            timed_out: false,
+            stdout: String::new(),
+            stderr: String::new(),
+        };
+        let timeout = Duration::from_secs(timeout.unwrap_or(self.default_timeout));
+        let supervisor = self.clone();
+
+        tokio::spawn(async move {
+            // The permit is held until the execution has been recorded.
+            let _permit = supervisor.acquire().await;
+            debug!("Running hook {}: {}", execution.name, execution.command);
+            let output = supervisor.execute_now(command, timeout).await;
+            execution.started_at = output.started_at;
+            execution.duration_ms = output.duration.as_millis() as i64;
+            execution.exit_code = output.exit_code;
+            execution.timed_out = output.timed_out;
+            execution.stdout = output.stdout;
+            execution.stderr = output.stderr;
+
+            if execution.timed_out {
+                warn!(
+                    "Hook {} timed out after {}s and has been killed",
+                    execution.name,
+                    timeout.as_secs()
+                );
+            } else if execution.exit_code != Some(0) {
+                warn!(
+                    "Hook {} failed with exit code {:?}: {}",
+                    execution.name, execution.exit_code, execution.stderr
+                );
+            }
+
+            supervisor.record(execution).await;
+        });
+    }
+
+    /// Execute a command, once fewer than `max_concurrent` commands are running.
+    ///
+    /// Only the first `output_limit` bytes of stdout/stderr are kept, the rest is discarded
+    /// while reading. If the timeout is hit, the whole process group of the command is killed.
+    pub async fn execute(&self, command: Command, timeout: Duration) -> CommandOutput {
+        let _permit = self.acquire().await;
+        self.execute_now(command, timeout).await
+    }
+
+    /// Wait until all commands, that have been started or queued so far, are done.
+    pub async fn wait_idle(&self) {
+        // Waiting commands are served first, which is why this waits for them as well.
+        let _permits = self
+            .semaphore
+            .acquire_many(self.max_concurrent)
+            .await
+            .expect("Semaphore has been closed");
+    }
+
+    /// Wait until we're allowed to run another command.
+    async fn acquire(&self) -> SemaphorePermit<'_> {
+        // The semaphore is never closed, which is why this cannot fail.
+        self.semaphore
+            .acquire()
+            .await
+            .expect("Semaphore has been closed")
+    }
+
+    async fn execute_now(&self, command: Command, timeout: Duration) -> CommandOutput {
+        let started_at = Utc::now();
+        let start = Instant::now();
+        let output = self.spawn_and_wait(command, timeout).await;
+
+        CommandOutput {
+            started_at,
+            duration: start.elapsed(),
+            ..output
+        }
+    }
+
+    async fn spawn_and_wait(&self, mut command: Command, timeout: Duration) -> CommandOutput {
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                return CommandOutput {
+                    stderr: format!("Failed to spawn command: {}", err),
+                    ..CommandOutput::default()
+                }
+            }
+        };
+        let process_group = child.id();
+        let stdout = child.stdout.take().expect("Stdout is piped");
+        let stderr = child.stderr.take().expect("Stderr is piped");
+
+        // Processes in the background might keep the pipes open after the shell exited.
+        // That's why the command is only done, once both pipes are closed as well.
+        let result = tokio::time::timeout(timeout, async {
+            tokio::join!(
+                child.wait(),
+                read_limited(stdout, self.output_limit),
+                read_limited(stderr, self.output_limit),
+            )
+        })
+        .await;
+
+        match result {
+            Ok((Ok(status), stdout, stderr)) => CommandOutput {
+                exit_code: status.code(),
+                stdout,
+                stderr,
+                ..CommandOutput::default()
+            },
+            Ok((Err(err), _, _)) => CommandOutput {
+                stderr: err.to_string(),
+                ..CommandOutput::default()
+            },
+            Err(_) => {
+                if let Some(process_group) = process_group {
+                    kill_process_group(process_group);
+                }
+                CommandOutput {
+                    timed_out: true,
+                    ..CommandOutput::default()
+                }
+            }
+        }
+    }
+
+    /// Write an execution into the audit log.
+    async fn record(&self, mut execution: HookExecution) {
+        let result = match self.pool.acquire().await {
+            Ok(mut connection) => execution.persist(&mut connection).await.map(|_| ()),
+            Err(err) => Err(err.into()),
+        };
+
+        if let Err(err) = result {
+            error!("Failed to record hook execution: {:?}", err);
+        }
+    }
+}
+
+/// Create a command, that's executed via `sh -c` in its own process group.
+pub fn shell(shell_command: &str) -> Command {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(shell_command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true);
+
+    command
+}
+
+/// Kill all processes of a process group, whose id is the id of its leader.
+fn kill_process_group(process_group: u32) {
+    // A negative pid addresses the whole group.
+    // This fails, if all processes already exited, which is fine.
+    unsafe {
+        libc::kill(-(process_group as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+/// Read a pipe until it's closed and keep at most `limit` bytes.
+/// Everything beyond the limit is read as well, so the process doesn't block on a full pipe.
+async fn read_limited(mut pipe: impl AsyncRead + Unpin, limit: usize) -> String {
+    let mut output = Vec::new();
+    let mut buffer = [0; 4096];
+    let mut truncated = false;
+    loop {
+        let read = match pipe.read(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+
+        let remaining = limit - output.len();
+        if read > remaining {
+            truncated = true;
+        }
+        output.extend_from_slice(&buffer[..read.min(remaining)]);
+    }
+
+    let mut output = String::from_utf8_lossy(&output).into_owned();
+    if truncated {
+        output.push_str("...");
+    }
+
+    output
+}
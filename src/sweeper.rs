@@ -0,0 +1,166 @@
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// An additional capture interface, which sweeps its share of the supported channels.
+///
+/// Sweepers sweep at the same time as the first interface, which only has to sweep the channels
+/// that none of the sweepers support.
+pub struct Sweeper {
+    pub device: String,
+    /// The channels this interface is responsible for.
+    channels: Vec<i32>,
+    /// The index of the next channel.
+    position: usize,
+    /// Whether all channels have been visited during the current sweep.
+    finished: bool,
+    last_switch: DateTime<Utc>,
+}
+
+impl Sweeper {
+    pub fn new(device: String) -> Self {
+        Sweeper {
+            device,
+            channels: Vec::new(),
+            position: 0,
+            finished: false,
+            last_switch: Utc::now(),
+        }
+    }
+
+    /// Split all channels between the sweepers.
+    /// Each channel is assigned to the sweeper with the fewest channels, that supports it.
+    pub fn split_channels(sweepers: &mut [Sweeper], supported_channels: &[Vec<i32>]) {
+        let mut channels: Vec<i32> = supported_channels.iter().flatten().copied().collect();
+        channels.sort_unstable();
+        channels.dedup();
+
+        for channel in channels {
+            let sweeper = sweepers
+                .iter_mut()
+                .zip(supported_channels)
+                .filter(|(_, supported)| supported.contains(&channel))
+                .map(|(sweeper, _)| sweeper)
+                .min_by_key(|sweeper| sweeper.channels.len());
+            if let Some(sweeper) = sweeper {
+                sweeper.channels.push(channel);
+            }
+        }
+    }
+
+    /// Get the next channel, once the current one has been watched for long enough.
+    /// Once all channels have been visited, the sweeper waits for the next sweep.
+    pub fn next_channel(&mut self, now: DateTime<Utc>, timeout: TimeDelta) -> Option<i32> {
+        if self.finished || self.channels.is_empty() || now - self.last_switch <= timeout {
+            return None;
+        }
+
+        // The last channel has been watched for long enough, we're through.
+        if self.position >= self.channels.len() {
+            self.position = 0;
+            self.finished = true;
+            return None;
+        }
+        let channel = self.channels[self.position];
+        self.position += 1;
+        self.last_switch = now;
+
+        Some(channel)
+    }
+
+    /// The amount of channels, that have been visited during the current sweep.
+    pub fn done(&self) -> usize {
+        if self.finished {
+            self.channels.len()
+        } else {
+            self.position
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Check whether all sweepers visited all of their channels.
+    /// If that's the case, they're reset for the next sweep.
+    /// Sweepers without any channels never sweep and are ignored.
+    pub fn sweep_finished(sweepers: &mut [Sweeper]) -> bool {
+        let finished = sweepers
+            .iter()
+            .filter(|sweeper| !sweeper.channels.is_empty())
+            .all(|sweeper| sweeper.finished);
+        if !finished {
+            return false;
+        }
+
+        for sweeper in sweepers.iter_mut() {
+            sweeper.finished = false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn time(hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 17, hour, minute, second)
+            .unwrap()
+    }
+
+    fn sweepers(count: usize) -> Vec<Sweeper> {
+        (0..count)
+            .map(|index| Sweeper::new(format!("wlan{}", index + 1)))
+            .collect()
+    }
+
+    #[test]
+    fn splits_channels_evenly() {
+        let mut sweepers = sweepers(2);
+        let supported = vec![vec![1, 2, 3, 4], vec![1, 2, 3, 4]];
+        Sweeper::split_channels(&mut sweepers, &supported);
+
+        assert_eq!(sweepers[0].channels, vec![1, 3]);
+        assert_eq!(sweepers[1].channels, vec![2, 4]);
+    }
+
+    #[test]
+    fn splits_channels_by_support() {
+        let mut sweepers = sweepers(3);
+        let supported = vec![vec![1, 6, 11], vec![1, 6, 11, 36, 40], vec![]];
+        Sweeper::split_channels(&mut sweepers, &supported);
+
+        // Each channel is only swept once and only by sweepers that support it.
+        assert_eq!(sweepers[0].channels, vec![1, 11]);
+        assert_eq!(sweepers[1].channels, vec![6, 36, 40]);
+        assert!(sweepers[2].channels.is_empty());
+    }
+
+    #[test]
+    fn sweeps_each_channel_once() {
+        let mut sweepers = sweepers(2);
+        Sweeper::split_channels(&mut sweepers, &[vec![1, 6], vec![]]);
+        let timeout = TimeDelta::try_seconds(1).unwrap();
+
+        let sweeper = &mut sweepers[0];
+        sweeper.last_switch = time(12, 0, 0);
+        assert_eq!(sweeper.next_channel(time(12, 0, 1), timeout), None);
+        assert_eq!(sweeper.next_channel(time(12, 0, 2), timeout), Some(1));
+        assert_eq!(sweeper.next_channel(time(12, 0, 3), timeout), None);
+        assert_eq!(sweeper.next_channel(time(12, 0, 4), timeout), Some(6));
+        assert_eq!(sweeper.done(), 2);
+        assert!(!Sweeper::sweep_finished(&mut sweepers));
+
+        // The last channel is watched for long enough as well, before the sweeper is done.
+        let sweeper = &mut sweepers[0];
+        assert_eq!(sweeper.next_channel(time(12, 0, 6), timeout), None);
+        assert_eq!(sweeper.next_channel(time(12, 0, 8), timeout), None);
+        assert_eq!(sweeper.done(), 2);
+
+        // Sweepers without channels don't hold up the sweep.
+        assert!(Sweeper::sweep_finished(&mut sweepers));
+        assert_eq!(sweepers[0].done(), 0);
+        assert_eq!(sweepers[0].next_channel(time(14, 0, 0), timeout), Some(1));
+    }
+}
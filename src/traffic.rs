@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Timelike, Utc};
+
+use crate::db::models::*;
+use crate::events::Event;
+use crate::presence::Activity;
+
+/// The traffic of a device on a station during a single minute.
+struct Bucket {
+    device: Device,
+    station: Station,
+    minute: DateTime<Utc>,
+    bytes: i32,
+    last_seen: DateTime<Utc>,
+}
+
+/// Sums up the traffic of each device per minute, the same way it's stored in the `data` table.
+/// Once a minute is over, a [Event::Traffic] is emitted for each device that has been active.
+#[derive(Default)]
+pub struct TrafficAggregator {
+    buckets: HashMap<(i32, i32), Bucket>,
+}
+
+impl TrafficAggregator {
+    /// Add the traffic of some activity to the current minute.
+    /// If the activity belongs to a newer minute, the previous minute is returned as an event.
+    pub fn handle_activity(&mut self, activity: &Activity) -> Option<Event> {
+        let key = (activity.device.id, activity.station.id);
+        let minute = get_minute(activity.time);
+
+        let finished = match self.buckets.get_mut(&key) {
+            Some(bucket) if bucket.minute == minute => {
+                bucket.bytes += activity.bytes;
+                if activity.time > bucket.last_seen {
+                    bucket.last_seen = activity.time;
+                }
+                return None;
+            }
+            // Packet handlers run concurrently, which is why activities might arrive out of order.
+            // Late activity of an already finished minute is dropped.
+            Some(bucket) if bucket.minute > minute => return None,
+            Some(_) => self.buckets.remove(&key),
+            None => None,
+        };
+
+        self.buckets.insert(
+            key,
+            Bucket {
+                device: activity.device.clone(),
+                station: activity.station.clone(),
+                minute,
+                bytes: activity.bytes,
+                last_seen: activity.time,
+            },
+        );
+
+        finished.map(Bucket::into_event)
+    }
+
+    /// Return events for all minutes that're over.
+    pub fn flush(&mut self, now: DateTime<Utc>) -> Vec<Event> {
+        let minute = get_minute(now);
+        let finished: Vec<(i32, i32)> = self
+            .buckets
+            .iter()
+            .filter(|(_, bucket)| bucket.minute < minute)
+            .map(|(key, _)| *key)
+            .collect();
+
+        finished
+            .iter()
+            .filter_map(|key| self.buckets.remove(key))
+            .map(Bucket::into_event)
+            .collect()
+    }
+}
+
+impl Bucket {
+    fn into_event(self) -> Event {
+        Event::Traffic {
+            device: self.device,
+            station: self.station,
+            time: self.minute,
+            bytes_per_minute: self.bytes,
+            last_seen: self.last_seen,
+        }
+    }
+}
+
+/// Round a time down to the start of its minute.
+pub fn get_minute(time: DateTime<Utc>) -> DateTime<Utc> {
+    time.with_second(0).unwrap().with_nanosecond(0).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn time(minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 17, 12, minute, second)
+            .unwrap()
+    }
+
+    fn activity(device: i32, time: DateTime<Utc>, bytes: i32) -> Activity {
+        Activity {
+            device: Device {
+                id: device,
+                mac_address: format!("02:00:00:00:00:{:02x}", device).parse().unwrap(),
+                nickname: None,
+                description: None,
+                watch: true,
+                vendor: None,
+                quarantined: false,
+            },
+            station: Station {
+                id: 1,
+                mac_address: "02:00:00:00:01:01".parse().unwrap(),
+                ssid: Some("home".into()),
+                channel: 6,
+                power_level: None,
+                nickname: None,
+                description: None,
+                watch: true,
+                protected: Some(true),
+            },
+            time,
+            bytes,
+            rssi: None,
+        }
+    }
+
+    fn bytes(event: &Event) -> (DateTime<Utc>, i32) {
+        match event {
+            Event::Traffic {
+                time,
+                bytes_per_minute,
+                ..
+            } => (*time, *bytes_per_minute),
+            _ => panic!("Expected a traffic event, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn sums_up_traffic_per_minute() {
+        let mut traffic = TrafficAggregator::default();
+        assert!(traffic
+            .handle_activity(&activity(1, time(0, 5), 100))
+            .is_none());
+        assert!(traffic
+            .handle_activity(&activity(1, time(0, 50), 50))
+            .is_none());
+
+        // The first frame of the next minute finishes the previous one.
+        let event = traffic
+            .handle_activity(&activity(1, time(1, 0), 10))
+            .unwrap();
+        assert_eq!(bytes(&event), (time(0, 0), 150));
+    }
+
+    #[test]
+    fn drops_late_activity() {
+        let mut traffic = TrafficAggregator::default();
+        traffic.handle_activity(&activity(1, time(1, 0), 10));
+        assert!(traffic
+            .handle_activity(&activity(1, time(0, 59), 100))
+            .is_none());
+
+        let events = traffic.flush(time(2, 0));
+        assert_eq!(events.len(), 1);
+        assert_eq!(bytes(&events[0]), (time(1, 0), 10));
+    }
+
+    #[test]
+    fn flushes_finished_minutes() {
+        let mut traffic = TrafficAggregator::default();
+        traffic.handle_activity(&activity(1, time(0, 30), 100));
+        traffic.handle_activity(&activity(2, time(1, 30), 200));
+
+        // Only the minute, that's already over, is flushed.
+        let events = traffic.flush(time(1, 45));
+        assert_eq!(events.len(), 1);
+        assert_eq!(bytes(&events[0]), (time(0, 0), 100));
+        assert!(traffic.flush(time(1, 59)).is_empty());
+
+        let events = traffic.flush(time(2, 0));
+        assert_eq!(events.len(), 1);
+        assert_eq!(bytes(&events[0]), (time(1, 0), 200));
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Maps the OUI (the first three bytes) of mac addresses to their vendor.
+#[derive(Default)]
+pub struct VendorDatabase {
+    /// The vendor for each OUI in upper case, e.g. `00:1B:63`.
+    vendors: HashMap<String, String>,
+}
+
+impl VendorDatabase {
+    /// Load the vendors from a Wireshark `manuf` file.
+    ///
+    /// Each line contains an OUI, a short name and an optional long name, separated by tabs.
+    /// Entries for smaller address blocks (e.g. `00:55:DA:00:00:00/28`) are skipped.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).context(format!("Couldn't open vendor file {:?}", path))?;
+
+        let mut vendors = HashMap::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.starts_with('#') {
+                continue;
+            }
+
+            let mut columns = line.split('\t');
+            let (oui, short_name) = match (columns.next(), columns.next()) {
+                (Some(oui), Some(short_name)) if oui.len() == 8 => (oui, short_name),
+                _ => continue,
+            };
+            let name = columns.next().unwrap_or(short_name);
+
+            vendors.insert(oui.to_uppercase(), name.trim().to_string());
+        }
+
+        Ok(VendorDatabase { vendors })
+    }
+
+    /// Get the vendor of a mac address in its usual `aa:bb:cc:dd:ee:ff` representation.
+    pub fn lookup(&self, mac_address: &str) -> Option<String> {
+        // Locally administered addresses don't have an OUI.
+        // Those are mostly used by phones, that randomize their mac address.
+        let first_byte = u8::from_str_radix(mac_address.get(0..2)?, 16).ok()?;
+        if first_byte & 0x02 != 0 {
+            return Some("Randomized".into());
+        }
+
+        self.vendors
+            .get(&mac_address.get(0..8)?.to_uppercase())
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> VendorDatabase {
+        let path = std::env::temp_dir().join(format!("wifitify-manuf-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "# Comment\n\
+             00:1B:63\tApple\tApple, Inc.\n\
+             f4:f5:d8\tGoogle\n\
+             00:55:DA:00:00:00/28\tSmall\tSmall block\n",
+        )
+        .unwrap();
+        let database = VendorDatabase::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        database
+    }
+
+    #[test]
+    fn looks_up_vendors() {
+        let database = database();
+        assert_eq!(
+            database.lookup("00:1b:63:12:34:56").as_deref(),
+            Some("Apple, Inc.")
+        );
+        // Entries without a long name use the short name.
+        assert_eq!(
+            database.lookup("F4:F5:D8:12:34:56").as_deref(),
+            Some("Google")
+        );
+        assert_eq!(database.lookup("00:55:da:00:00:01"), None);
+        assert_eq!(database.lookup("00:11:22:33:44:55"), None);
+    }
+
+    #[test]
+    fn detects_randomized_addresses() {
+        let database = VendorDatabase::default();
+        assert_eq!(
+            database.lookup("da:a1:19:12:34:56").as_deref(),
+            Some("Randomized")
+        );
+        assert_eq!(database.lookup("d8:a1:19:12:34:56"), None);
+        assert_eq!(database.lookup("invalid"), None);
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{TimeDelta, Utc};
+use log::{debug, error, warn};
+use reqwest::Client;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use crate::config::Webhooks;
+use crate::db::models::WebhookDelivery;
+use crate::db::DbPool;
+use crate::events::Event;
+
+/// Spawn the tasks that queue and deliver webhooks.
+///
+/// Each event is first written to the `webhook_deliveries` table.
+/// A second task then tries to deliver all queued requests and reschedules failed deliveries.
+/// That way, no events get lost if the receiving side or the daemon itself is restarted.
+///
+/// Returns the task that queues the events, which stops once the event bus is gone.
+pub fn spawn_webhook_delivery(
+    config: Webhooks,
+    pool: DbPool,
+    receiver: Receiver<Event>,
+) -> Option<JoinHandle<()>> {
+    if config.endpoints.is_empty() {
+        return None;
+    }
+
+    let client = match Client::builder()
+        .timeout(Duration::from_secs(config.timeout))
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            error!("Failed to create http client for webhooks: {:?}", err);
+            return None;
+        }
+    };
+
+    // Used to wake up the delivery task as soon as a new event has been queued.
+    let notify = Arc::new(Notify::new());
+
+    let queue = tokio::spawn(queue_events(
+        config.clone(),
+        pool.clone(),
+        receiver,
+        notify.clone(),
+    ));
+    tokio::spawn(deliver(config, pool, client, notify));
+
+    Some(queue)
+}
+
+/// Write all incoming events into the delivery queue.
+async fn queue_events(
+    config: Webhooks,
+    pool: DbPool,
+    mut receiver: Receiver<Event>,
+    notify: Arc<Notify>,
+) {
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(count)) => {
+                warn!("Webhook queue couldn't keep up and missed {} events", count);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        if let Err(err) = queue_event(&config, &pool, &event).await {
+            error!("Failed to queue webhook for event: {:?}", err);
+        }
+        notify.notify_one();
+    }
+}
+
+async fn queue_event(config: &Webhooks, pool: &DbPool, event: &Event) -> Result<()> {
+    let payload = serde_json::to_string(event)?;
+    let mut connection = pool.acquire().await?;
+
+    for endpoint in config.endpoints.iter() {
+        if !event.kind().selected_by(&endpoint.events) {
+            continue;
+        }
+
+        let mut delivery = WebhookDelivery {
+            id: 0,
+            url: endpoint.url.clone(),
+            payload: payload.clone(),
+            attempts: 0,
+            next_attempt: Utc::now(),
+            last_error: None,
+        };
+        delivery.persist(&mut connection).await?;
+    }
+
+    Ok(())
+}
+
+/// Periodically try to deliver all due webhooks.
+async fn deliver(config: Webhooks, pool: DbPool, client: Client, notify: Arc<Notify>) {
+    loop {
+        if let Err(err) = deliver_due(&config, &pool, &client).await {
+            error!("Failed to deliver webhooks: {:?}", err);
+        }
+
+        // Wait until either a new event has been queued or the next retry might be due.
+        let _ = tokio::time::timeout(Duration::from_secs(1), notify.notified()).await;
+    }
+}
+
+async fn deliver_due(config: &Webhooks, pool: &DbPool, client: &Client) -> Result<()> {
+    let due = {
+        let mut connection = pool.acquire().await?;
+        WebhookDelivery::due(&mut connection, Utc::now()).await?
+    };
+
+    // The connection is only held while updating a delivery, not during the requests.
+    for mut delivery in due {
+        let result = client
+            .post(&delivery.url)
+            .header("Content-Type", "application/json")
+            .body(delivery.payload.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        let mut connection = pool.acquire().await?;
+        let err = match result {
+            Ok(_) => {
+                debug!("Delivered webhook to {}", delivery.url);
+                delivery.delete(&mut connection).await?;
+                continue;
+            }
+            Err(err) => err,
+        };
+
+        delivery.attempts += 1;
+        if delivery.attempts >= config.max_attempts {
+            error!(
+                "Giving up on webhook to {} after {} attempts: {:?}",
+                delivery.url, delivery.attempts, err
+            );
+            delivery.delete(&mut connection).await?;
+            continue;
+        }
+
+        // Exponential backoff, capped at the maximum retry interval.
+        let backoff = config
+            .retry_interval
+            .saturating_mul(1 << (delivery.attempts - 1).min(20))
+            .min(config.max_retry_interval);
+        warn!(
+            "Failed to deliver webhook to {}, retrying in {}s: {:?}",
+            delivery.url, backoff, err
+        );
+
+        delivery.next_attempt = Utc::now() + TimeDelta::try_seconds(backoff).unwrap();
+        delivery.last_error = Some(err.to_string());
+        delivery.update_attempt(&mut connection).await?;
+    }
+
+    Ok(())
+}
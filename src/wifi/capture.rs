@@ -1,6 +1,10 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
 use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
 use log::debug;
-use pcap::{Active, Capture, Device};
+use pcap::{Active, Capture, Device, Linktype, Offline};
 
 use libwifi::error::Error;
 use libwifi::*;
@@ -8,7 +12,10 @@ use pcap::Packet;
 use radiotap::Radiotap;
 
 /// Parse the packet received by [pcap](::pcap)
-pub fn handle_packet(packet: Packet) -> Result<(Frame, Radiotap)> {
+/// The frame is returned together with the time at which it has been captured.
+pub fn handle_packet(packet: Packet) -> Result<(Frame, Radiotap, DateTime<Utc>)> {
+    let time = packet_time(&packet);
+
     // Read the raw payload, which
     let radiotap = Radiotap::from_bytes(packet.data)?;
 
@@ -40,7 +47,7 @@ pub fn handle_packet(packet: Packet) -> Result<(Frame, Radiotap)> {
         frame.unwrap()
     };
 
-    Ok((frame, radiotap))
+    Ok((frame, radiotap, time))
 }
 
 /// Initializes and configures a network device by name.
@@ -63,6 +70,47 @@ pub fn get_capture(device_name: &str) -> Result<Capture<Active>> {
     Ok(capture)
 }
 
+/// Open a recorded capture file (pcap or pcapng).
+/// The file has to contain IEEE 802.11 frames with radiotap headers.
+pub fn get_file_capture(path: &Path) -> Result<Capture<Offline>> {
+    let capture =
+        Capture::from_file(path).context(format!("Failed to open capture file {:?}", path))?;
+
+    // DLT_IEEE802_11_RADIO = 127, same as for live captures.
+    let datalink = capture.get_datalink();
+    if datalink != Linktype(127) {
+        bail!(
+            "Capture file {:?} doesn't contain radiotap frames (datalink {})",
+            path,
+            datalink.0
+        );
+    }
+
+    Ok(capture)
+}
+
+/// The time at which a packet has been captured, according to its pcap header.
+pub fn packet_time(packet: &Packet) -> DateTime<Utc> {
+    DateTime::from_timestamp(
+        packet.header.ts.tv_sec as i64,
+        packet.header.ts.tv_usec.max(0) as u32 * 1000,
+    )
+    .unwrap_or_else(Utc::now)
+}
+
+/// Build the BPF filter program, which only keeps beacons and frames from or to watched stations.
+/// Beacons are always needed to discover new stations.
+pub fn watch_list_filter(bssids: &BTreeSet<String>) -> String {
+    let mut filter = String::from("type mgt subtype beacon");
+    for bssid in bssids {
+        filter.push_str(&format!(
+            " or wlan addr1 {bssid} or wlan addr2 {bssid} or wlan addr3 {bssid}"
+        ));
+    }
+
+    filter
+}
+
 /// Check if a device with a given name exists.
 /// If that's the case, return it.
 fn find_device_by_name(name: &str) -> Result<Device> {
@@ -76,3 +124,32 @@ fn find_device_by_name(name: &str) -> Result<Device> {
 
     bail!("Couldn't find device with name {}", name)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_without_watched_stations() {
+        assert_eq!(
+            watch_list_filter(&BTreeSet::new()),
+            "type mgt subtype beacon"
+        );
+    }
+
+    #[test]
+    fn filter_with_watched_stations() {
+        let bssids: BTreeSet<String> = ["aa:bb:cc:dd:ee:ff", "00:11:22:33:44:55"]
+            .iter()
+            .map(|bssid| bssid.to_string())
+            .collect();
+
+        // The stations are sorted, which keeps the filter stable between updates.
+        assert_eq!(
+            watch_list_filter(&bssids),
+            "type mgt subtype beacon \
+             or wlan addr1 00:11:22:33:44:55 or wlan addr2 00:11:22:33:44:55 or wlan addr3 00:11:22:33:44:55 \
+             or wlan addr1 aa:bb:cc:dd:ee:ff or wlan addr2 aa:bb:cc:dd:ee:ff or wlan addr3 aa:bb:cc:dd:ee:ff"
+        );
+    }
+}
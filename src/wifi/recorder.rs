@@ -0,0 +1,309 @@
+use std::collections::HashSet;
+use std::fs::{create_dir_all, read_dir, remove_file, File};
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use log::{error, info};
+use pcap::Packet;
+
+use crate::config::Recording;
+
+/// The prefix and extension of all recordings, which are used to find old recordings.
+const FILE_PREFIX: &str = "wifitify-";
+const FILE_EXTENSION: &str = ".pcapng";
+
+/// Buffered frames are written to disk at least this often.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The link type for IEEE 802.11 frames with radiotap headers.
+const LINKTYPE_IEEE802_11_RADIO: u16 = 127;
+
+/// The bssids of all watched stations in lower case.
+/// These are shared with the main thread, which updates them once the stations have been loaded.
+pub type WatchedStations = Arc<RwLock<HashSet<String>>>;
+
+/// The recorder is shared between the listener thread and the main thread.
+/// The main thread flushes it on shutdown.
+pub type SharedRecorder = Arc<Mutex<Recorder>>;
+
+/// The pcapng file that's currently being written.
+struct RecordingFile {
+    writer: BufWriter<File>,
+    size: u64,
+    started: Instant,
+    last_flush: Instant,
+}
+
+/// Writes the raw captured frames to pcapng files, which can be inspected via Wireshark.
+///
+/// A new file is started, once the current one is too large or too old.
+/// Only the newest `max_files` recordings are kept.
+pub struct Recorder {
+    config: Recording,
+    watched_stations: WatchedStations,
+    current: Option<RecordingFile>,
+    /// The amount of files that have been started, which keeps their names unique.
+    sequence: u32,
+}
+
+impl Recorder {
+    pub fn new(config: &Recording, watched_stations: WatchedStations) -> Result<Self> {
+        create_dir_all(&config.directory).context(format!(
+            "Couldn't create recording directory {:?}",
+            config.directory
+        ))?;
+
+        Ok(Recorder {
+            config: config.clone(),
+            watched_stations,
+            current: None,
+            sequence: 0,
+        })
+    }
+
+    /// Write a captured packet to the current recording.
+    /// Errors are only logged, since recording must never stop the capture.
+    pub fn record(&mut self, packet: &Packet) {
+        if self.config.watched_only && !self.involves_watched_station(packet.data) {
+            return;
+        }
+
+        if let Err(err) = self.write(packet) {
+            error!("Failed to record frame: {:?}", err);
+            // Keep the frames recorded so far and start with a new file for the next frame.
+            self.flush();
+            self.current = None;
+        }
+    }
+
+    /// Write all buffered frames to disk.
+    pub fn flush(&mut self) {
+        if let Some(current) = self.current.as_mut() {
+            if let Err(err) = current.writer.flush() {
+                error!("Failed to flush recording: {:?}", err);
+            }
+            current.last_flush = Instant::now();
+        }
+    }
+
+    fn write(&mut self, packet: &Packet) -> Result<()> {
+        let needs_rotation = match &self.current {
+            Some(current) => {
+                current.size >= self.config.max_file_size * 1024 * 1024
+                    || current.started.elapsed() >= Duration::from_secs(self.config.max_file_age)
+            }
+            None => true,
+        };
+        if needs_rotation {
+            self.rotate()?;
+        }
+        let current = self.current.as_mut().expect("A file has just been opened");
+
+        // Timestamps are microseconds since the epoch, which is the default resolution.
+        let timestamp = packet.header.ts.tv_sec.max(0) as u64 * 1_000_000
+            + packet.header.ts.tv_usec.max(0) as u64;
+        let block = packet_block(timestamp, packet.data, packet.header.len);
+
+        current.writer.write_all(&block)?;
+        current.size += block.len() as u64;
+
+        if current.last_flush.elapsed() >= FLUSH_INTERVAL {
+            current.writer.flush()?;
+            current.last_flush = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    /// Close the current recording and start a new one.
+    /// Afterwards, the oldest recordings are removed.
+    fn rotate(&mut self) -> Result<()> {
+        if let Some(mut current) = self.current.take() {
+            current.writer.flush()?;
+        }
+
+        // Files might be rotated several times per second, e.g. after write errors.
+        // The time is in UTC, so the names stay sorted by age across daylight saving time changes.
+        self.sequence += 1;
+        let name = format!(
+            "{}{}-{:04}{}",
+            FILE_PREFIX,
+            Utc::now().format("%Y%m%d-%H%M%S%.3f"),
+            self.sequence,
+            FILE_EXTENSION
+        );
+        let path = self.config.directory.join(name);
+        let file = File::create(&path).context(format!("Couldn't create recording {:?}", path))?;
+        info!("Recording frames to {:?}", path);
+
+        let mut writer = BufWriter::new(file);
+        let header = file_header();
+        writer.write_all(&header)?;
+        self.current = Some(RecordingFile {
+            writer,
+            size: header.len() as u64,
+            started: Instant::now(),
+            last_flush: Instant::now(),
+        });
+
+        self.remove_old_recordings()
+    }
+
+    /// Only keep the newest recordings.
+    /// The file names contain the start time, which is why they're sorted by age.
+    fn remove_old_recordings(&self) -> Result<()> {
+        let mut recordings = Vec::new();
+        for entry in read_dir(&self.config.directory)? {
+            let path = entry?.path();
+            let is_recording = path.file_name().is_some_and(|name| {
+                let name = name.to_string_lossy();
+                name.starts_with(FILE_PREFIX) && name.ends_with(FILE_EXTENSION)
+            });
+            if is_recording {
+                recordings.push(path);
+            }
+        }
+        recordings.sort();
+
+        let excess = recordings.len().saturating_sub(self.config.max_files);
+        for path in recordings.into_iter().take(excess) {
+            info!("Removing old recording {:?}", path);
+            remove_file(&path).context(format!("Couldn't remove recording {:?}", path))?;
+        }
+
+        Ok(())
+    }
+
+    /// Check whether any address of the 802.11 header is one of our watched stations.
+    fn involves_watched_station(&self, data: &[u8]) -> bool {
+        // The radiotap header is followed by the 802.11 header.
+        // Its addresses start after the frame control and duration fields.
+        let radiotap_length = match data.get(2..4) {
+            Some(length) => u16::from_le_bytes([length[0], length[1]]) as usize,
+            None => return false,
+        };
+        let header = match data.get(radiotap_length..) {
+            Some(header) => header,
+            None => return false,
+        };
+
+        let watched_stations = match self.watched_stations.read() {
+            Ok(watched_stations) => watched_stations,
+            Err(_) => return false,
+        };
+
+        // Control frames only have one or two addresses.
+        (0..3)
+            .filter_map(|index| header.get(4 + index * 6..10 + index * 6))
+            .any(|address| {
+                let address: Vec<String> =
+                    address.iter().map(|byte| format!("{:02x}", byte)).collect();
+                watched_stations.contains(&address.join(":"))
+            })
+    }
+}
+
+/// The section header and interface description blocks at the start of each file.
+fn file_header() -> Vec<u8> {
+    let mut header = Vec::new();
+
+    // Section header block
+    header.extend_from_slice(&0x0A0D_0D0Au32.to_le_bytes());
+    header.extend_from_slice(&28u32.to_le_bytes());
+    header.extend_from_slice(&0x1A2B_3C4Du32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes());
+    // The length of the section is unknown.
+    header.extend_from_slice(&(-1i64).to_le_bytes());
+    header.extend_from_slice(&28u32.to_le_bytes());
+
+    // Interface description block
+    header.extend_from_slice(&1u32.to_le_bytes());
+    header.extend_from_slice(&20u32.to_le_bytes());
+    header.extend_from_slice(&LINKTYPE_IEEE802_11_RADIO.to_le_bytes());
+    header.extend_from_slice(&0u16.to_le_bytes());
+    // No limit for the amount of captured bytes per packet.
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&20u32.to_le_bytes());
+
+    header
+}
+
+/// An enhanced packet block for a single captured packet.
+/// `original_length` is the length of the packet on the air, which might be larger than `data`.
+fn packet_block(timestamp: u64, data: &[u8], original_length: u32) -> Vec<u8> {
+    let padding = (4 - data.len() % 4) % 4;
+    let length = (32 + data.len() + padding) as u32;
+
+    let mut block = Vec::with_capacity(length as usize);
+    block.extend_from_slice(&6u32.to_le_bytes());
+    block.extend_from_slice(&length.to_le_bytes());
+    block.extend_from_slice(&0u32.to_le_bytes());
+    block.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+    block.extend_from_slice(&(timestamp as u32).to_le_bytes());
+    block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    block.extend_from_slice(&original_length.to_le_bytes());
+    block.extend_from_slice(data);
+    block.resize(block.len() + padding, 0);
+    block.extend_from_slice(&length.to_le_bytes());
+
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn encodes_file_header() {
+        let header = file_header();
+        assert_eq!(header.len(), 48);
+
+        // Section header block
+        assert_eq!(u32_at(&header, 0), 0x0A0D_0D0A);
+        assert_eq!(u32_at(&header, 4), 28);
+        assert_eq!(u32_at(&header, 8), 0x1A2B_3C4D);
+        assert_eq!(u32_at(&header, 24), 28);
+
+        // Interface description block
+        assert_eq!(u32_at(&header, 28), 1);
+        assert_eq!(u32_at(&header, 32), 20);
+        assert_eq!(&header[36..38], &LINKTYPE_IEEE802_11_RADIO.to_le_bytes());
+        assert_eq!(u32_at(&header, 44), 20);
+    }
+
+    #[test]
+    fn encodes_packet_blocks() {
+        let timestamp = 0x0006_1234_5678_9ABC;
+        let block = packet_block(timestamp, &[1, 2, 3, 4, 5], 7);
+
+        // The data is padded to 32 bits.
+        assert_eq!(block.len(), 40);
+        assert_eq!(u32_at(&block, 0), 6);
+        assert_eq!(u32_at(&block, 4), 40);
+        assert_eq!(u32_at(&block, 8), 0);
+        assert_eq!(u32_at(&block, 12), 0x0006_1234);
+        assert_eq!(u32_at(&block, 16), 0x5678_9ABC);
+        assert_eq!(u32_at(&block, 20), 5);
+        assert_eq!(u32_at(&block, 24), 7);
+        assert_eq!(&block[28..36], &[1, 2, 3, 4, 5, 0, 0, 0]);
+        assert_eq!(u32_at(&block, 36), 40);
+    }
+
+    #[test]
+    fn aligned_packets_are_not_padded() {
+        let block = packet_block(0, &[0; 8], 8);
+        assert_eq!(block.len(), 40);
+        assert_eq!(u32_at(&block, 4), 40);
+        assert_eq!(u32_at(&block, 36), 40);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::config::{Proximity, Zone};
+use crate::db::models::*;
+use crate::events::Event;
+use crate::presence::Activity;
+
+/// The smoothed signal strength of a single device on a single station.
+struct Signal {
+    device: Device,
+    station: Station,
+    /// The exponentially smoothed signal strength in dBm.
+    smoothed: f64,
+    /// The indices of all zones the device is currently in.
+    zones: HashSet<usize>,
+}
+
+/// Decides in which proximity zones devices are, based on the signal strength of their frames.
+pub struct ZoneTracker {
+    smoothing: f64,
+    hysteresis: f64,
+    zones: Vec<Zone>,
+    /// The signal for each `(device id, station id)` tuple.
+    signals: HashMap<(i32, i32), Signal>,
+}
+
+impl ZoneTracker {
+    pub fn new(config: &Proximity) -> Self {
+        ZoneTracker {
+            smoothing: config.smoothing.clamp(0.0, 1.0),
+            hysteresis: config.hysteresis as f64,
+            zones: config.zones.clone(),
+            signals: HashMap::new(),
+        }
+    }
+
+    /// Update the smoothed signal of a device with the signal strength of a frame.
+    /// Returns all zones the device entered or left.
+    pub fn handle_activity(&mut self, activity: &Activity) -> Vec<Event> {
+        let rssi = match activity.rssi {
+            Some(rssi) if !self.zones.is_empty() => rssi as f64,
+            _ => return Vec::new(),
+        };
+
+        let signal = self
+            .signals
+            .entry((activity.device.id, activity.station.id))
+            .or_insert_with(|| Signal {
+                device: activity.device.clone(),
+                station: activity.station.clone(),
+                smoothed: rssi,
+                zones: HashSet::new(),
+            });
+        signal.device = activity.device.clone();
+        signal.station = activity.station.clone();
+        signal.smoothed += self.smoothing * (rssi - signal.smoothed);
+
+        let mut events = Vec::new();
+        for (index, zone) in self.zones.iter().enumerate() {
+            if !matches_station(zone, &signal.station) {
+                continue;
+            }
+
+            // Devices that are inside a zone have to get out of its bounds by the hysteresis.
+            // Otherwise devices right at the border would keep entering and leaving.
+            let inside = signal.zones.contains(&index);
+            let margin = if inside { self.hysteresis } else { 0.0 };
+            let in_bounds = signal.smoothed >= zone.min_signal as f64 - margin
+                && zone
+                    .max_signal
+                    .map(|max| signal.smoothed <= max as f64 + margin)
+                    .unwrap_or(true);
+
+            if in_bounds && !inside {
+                signal.zones.insert(index);
+                events.push(Event::ZoneEntered {
+                    device: signal.device.clone(),
+                    station: signal.station.clone(),
+                    time: activity.time,
+                    zone: zone.name.clone(),
+                    signal: signal.smoothed.round() as i32,
+                });
+            } else if !in_bounds && inside {
+                signal.zones.remove(&index);
+                events.push(Event::ZoneLeft {
+                    device: signal.device.clone(),
+                    station: signal.station.clone(),
+                    time: activity.time,
+                    zone: zone.name.clone(),
+                    signal: Some(signal.smoothed.round() as i32),
+                });
+            }
+        }
+
+        events
+    }
+
+    /// Devices that left a station leave all of its zones as well.
+    pub fn handle_event(&mut self, event: &Event) -> Vec<Event> {
+        let (device, station, time) = match event {
+            Event::DeviceLeft {
+                device,
+                station,
+                time,
+                ..
+            } => (device, station, *time),
+            _ => return Vec::new(),
+        };
+
+        // The smoothed signal is dropped as well, since it's outdated once the device comes back.
+        let signal = match self.signals.remove(&(device.id, station.id)) {
+            Some(signal) => signal,
+            None => return Vec::new(),
+        };
+
+        let mut zones: Vec<usize> = signal.zones.into_iter().collect();
+        zones.sort_unstable();
+        zones
+            .into_iter()
+            .map(|index| Event::ZoneLeft {
+                device: device.clone(),
+                station: station.clone(),
+                time,
+                zone: self.zones[index].name.clone(),
+                signal: None,
+            })
+            .collect()
+    }
+}
+
+/// Check whether a zone belongs to a station.
+fn matches_station(zone: &Zone, station: &Station) -> bool {
+    match &zone.station {
+        Some(name) => {
+            station.nickname.as_ref() == Some(name)
+                || station.ssid.as_ref() == Some(name)
+                || &station.mac_address.to_string() == name
+        }
+        None => true,
+    }
+}